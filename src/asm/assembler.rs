@@ -0,0 +1,206 @@
+use super::{Archive, CodeGenerator, Image, LdScript, Linker, ObjectFile, SymbolFormat};
+use crate::{
+    asm::{AsmParser, Dialect},
+    errors::Diagnostics,
+};
+use std::path::PathBuf;
+
+/// A named piece of assembly source, usually the contents of a file.
+#[derive(Debug)]
+pub struct Source {
+    name: String,
+    text: String,
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str) -> Source {
+        Source {
+            name: name.into(),
+            text: text.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AsmOptions {
+    /// directories searched for `.include` files
+    pub include_paths: Vec<PathBuf>,
+    /// symbol whose address is recorded as the entry point of the image
+    pub entry: Option<String>,
+    /// definitions every source starts with, e.g. from the command line
+    pub defines: Option<Source>,
+    /// meaning of `;` in all sources and their included files
+    pub dialect: Dialect,
+}
+
+/// A linked program: the image to write out together with the linker that
+/// produced it, which can describe the result, e.g. as a listing.
+pub struct Program {
+    image: Image,
+    linker: Linker,
+    warnings: Diagnostics,
+}
+
+impl Program {
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Problems found in the sources that didn't stop the assembler
+    pub fn warnings(&self) -> &Diagnostics {
+        &self.warnings
+    }
+
+    pub fn listing(&self) -> String {
+        self.linker.listing()
+    }
+
+    pub fn map(&self) -> String {
+        self.linker.map()
+    }
+
+    pub fn export_symbols(&self, format: SymbolFormat) -> String {
+        self.linker.export_symbols(format)
+    }
+}
+
+/// Assembles every source on its own and links the results according to the
+/// given linker script. Labels and constants are local to the source that
+/// defines them unless exported with `.global`. Errors of all sources are
+/// reported together; linking is only attempted if there are none.
+pub fn assemble(sources: &[Source], ldscript: LdScript) -> Result<Image, Diagnostics> {
+    assemble_with_options(sources, ldscript, &AsmOptions::default())
+}
+
+pub fn assemble_with_options(
+    sources: &[Source],
+    ldscript: LdScript,
+    options: &AsmOptions,
+) -> Result<Image, Diagnostics> {
+    assemble_program(sources, ldscript, options).map(|program| program.image)
+}
+
+/// Same as [`assemble_with_options`], but keeps everything known about the
+/// linked program around.
+pub fn assemble_program(
+    sources: &[Source],
+    ldscript: LdScript,
+    options: &AsmOptions,
+) -> Result<Program, Diagnostics> {
+    // the definitions are part of every source, but their errors should
+    // only be reported once
+    if options.defines.is_some() {
+        compile(&[], options)?;
+    }
+
+    // warnings are reported together with the errors, if there are any
+    let mut objects = vec![];
+    let mut diagnostics = Diagnostics::new();
+    for source in sources.iter() {
+        match compile_with_warnings(std::slice::from_ref(source), options, &mut diagnostics) {
+            Ok(object) => objects.push(object),
+            Err(errors) => diagnostics.append(errors),
+        }
+    }
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+    match link(objects, ldscript, options) {
+        Ok(program) => Ok(Program {
+            warnings: diagnostics,
+            ..program
+        }),
+        Err(errors) => {
+            diagnostics.append(errors);
+            Err(diagnostics)
+        }
+    }
+}
+
+/// Parses all sources in order and generates code without linking. The
+/// sources share their symbols as if they were a single file. Symbols they
+/// don't define are resolved when linking the object file with others.
+pub fn compile(sources: &[Source], options: &AsmOptions) -> Result<ObjectFile, Diagnostics> {
+    compile_with_warnings(sources, options, &mut Diagnostics::new())
+}
+
+/// Same as [`compile`], but adds warnings to `warnings` if compiling
+/// succeeds. Otherwise they are part of the returned errors.
+pub fn compile_with_warnings(
+    sources: &[Source],
+    options: &AsmOptions,
+    warnings: &mut Diagnostics,
+) -> Result<ObjectFile, Diagnostics> {
+    let mut codegen = CodeGenerator::new();
+    let mut diagnostics = Diagnostics::new();
+    for source in options.defines.iter().chain(sources.iter()) {
+        let mut parser = AsmParser::with_dialect(&source.text, options.dialect);
+        parser.set_file_name(&source.name);
+        parser.set_include_paths(&options.include_paths);
+        parser.parse(&mut codegen);
+        diagnostics.append(parser.diagnostics(&source.name));
+    }
+
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+    match codegen.compile() {
+        Ok(object) => {
+            warnings.append(diagnostics);
+            Ok(object)
+        }
+        Err(errors) => {
+            diagnostics.append(errors);
+            Err(diagnostics)
+        }
+    }
+}
+
+/// Links object files according to the given linker script. Sections with
+/// the same name are placed one after another in the order of the objects.
+/// Objects only see each other's global symbols, defining one in more than
+/// one object is an error.
+pub fn link(
+    objects: Vec<ObjectFile>,
+    ldscript: LdScript,
+    options: &AsmOptions,
+) -> Result<Program, Diagnostics> {
+    link_with_archives(objects, vec![], ldscript, options)
+}
+
+/// Same as [`link`], but also adds the members of the archives that define
+/// symbols the objects refer to. The members are placed after the objects.
+pub fn link_with_archives(
+    objects: Vec<ObjectFile>,
+    archives: Vec<Archive>,
+    ldscript: LdScript,
+    options: &AsmOptions,
+) -> Result<Program, Diagnostics> {
+    let mut linker = Linker::new();
+    for object in objects {
+        linker.add_object(object);
+    }
+    for archive in archives {
+        linker.add_archive(archive);
+    }
+
+    let mut image = linker.link(ldscript)?;
+    if let Some(entry) = &options.entry {
+        match linker.find_symbol(entry) {
+            Some(addr) => image.set_entry(addr),
+            None => {
+                let message = format!("entry symbol {} is not defined", entry);
+                return Err(Diagnostics::from_messages(vec![message]));
+            }
+        }
+    }
+    Ok(Program {
+        image,
+        linker,
+        warnings: Diagnostics::new(),
+    })
+}
@@ -0,0 +1,131 @@
+use super::object::ObjectFile;
+use std::{collections::HashSet, fmt::Write};
+
+const HEADER: &str = "retro-lang archive 1";
+
+/// A static library: object files bundled with an index of the global
+/// symbols they define. When linking, only the members defining symbols that are still
+/// undefined are added to the program.
+///
+/// Archives are stored as text, the index first and then every member with
+/// the number of lines of its object file, e.g.
+///
+/// ```text
+/// retro-lang archive 1
+/// index putc 0
+/// index getc 0
+/// index strlen 1
+/// member serial.o 9
+/// retro-lang object 1
+/// ...
+/// member string.o 12
+/// retro-lang object 1
+/// ...
+/// ```
+#[derive(Default)]
+pub struct Archive {
+    // members are taken out once the linker added them to the program
+    members: Vec<(String, Option<ObjectFile>)>,
+    index: Vec<(String, usize)>,
+}
+
+impl Archive {
+    pub fn new() -> Archive {
+        Archive::default()
+    }
+
+    /// Adds a member and indexes the symbols it defines. Symbols already
+    /// defined by an earlier member keep pointing to that one.
+    pub fn add_member(&mut self, name: &str, object: ObjectFile) {
+        let member = self.members.len();
+        for symbol in object.global_symbols() {
+            if !self.index.iter().any(|(name, _)| name == symbol) {
+                self.index.push((symbol.into(), member));
+            }
+        }
+        self.members.push((name.into(), Some(object)));
+    }
+
+    pub fn member_names(&self) -> Vec<&str> {
+        self.members.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Whether the text looks like an archive rather than an object file
+    pub fn is_archive(text: &str) -> bool {
+        text.lines().next() == Some(HEADER)
+    }
+
+    pub fn write(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for (symbol, member) in self.index.iter() {
+            writeln!(text, "index {} {}", symbol, member).unwrap();
+        }
+        for (name, object) in self.members.iter() {
+            let object = object.as_ref().map(|object| object.write());
+            let object = object.as_deref().unwrap_or_default();
+            writeln!(text, "member {} {}", name, object.lines().count()).unwrap();
+            text.push_str(object);
+        }
+        text
+    }
+
+    /// Reads an archive written by `write`. Errors name the offending line
+    /// of the archive, or the member and the line within it.
+    pub fn read(text: &str) -> Result<Archive, String> {
+        let mut archive = Archive::new();
+        if !Archive::is_archive(text) {
+            return Err("not an archive".into());
+        }
+        let mut lines = text.lines().enumerate().skip(1);
+
+        while let Some((i, line)) = lines.next() {
+            let error = |message: String| format!("line {}: {}", i + 1, message);
+            let (kind, fields) = line.split_once(' ').unwrap_or((line, ""));
+            let (name, number) = fields
+                .rsplit_once(' ')
+                .ok_or_else(|| error("expected 2 fields".into()))?;
+            let number: usize = number
+                .parse()
+                .map_err(|_| error(format!("invalid number '{}'", number)))?;
+            match kind {
+                "index" => archive.index.push((name.into(), number)),
+                "member" => {
+                    let object: Vec<&str> = lines.by_ref().take(number).map(|(_, l)| l).collect();
+                    if object.len() != number {
+                        return Err(error(format!("member {} is truncated", name)));
+                    }
+                    let object = ObjectFile::read(&object.join("\n"))
+                        .map_err(|message| format!("member {}: {}", name, message))?;
+                    archive.members.push((name.into(), Some(object)));
+                }
+                _ => return Err(error(format!("unknown record '{}'", kind))),
+            }
+        }
+
+        let member_count = archive.members.len();
+        if let Some((symbol, _)) = archive.index.iter().find(|(_, m)| *m >= member_count) {
+            return Err(format!(
+                "index entry of {} refers to a missing member",
+                symbol
+            ));
+        }
+        Ok(archive)
+    }
+
+    /// Takes out the members defining any of the given symbols, in the order
+    /// they were added to the archive.
+    pub(super) fn take_members(&mut self, symbols: &HashSet<&str>) -> Vec<ObjectFile> {
+        let mut wanted: Vec<usize> = self
+            .index
+            .iter()
+            .filter(|(name, _)| symbols.contains(name.as_str()))
+            .map(|(_, member)| *member)
+            .collect();
+        wanted.sort();
+        wanted.dedup();
+        wanted
+            .into_iter()
+            .filter_map(|member| self.members[member].1.take())
+            .collect()
+    }
+}
@@ -1,23 +1,90 @@
-use std::{collections::HashMap};
+use super::{error_at, opcode_table::get_opcode, symtab::SymbolTable};
+use crate::asm::model::{
+    AddrMode, AsmStmt, DataPlacement, EvalError, Expr, IndexMode, Instruction, SourceLoc,
+    SourceStmt, UnaryOp,
+};
+use crate::errors::Diagnostic;
 
-use super::{opcode_table::get_opcode, symtab::SymbolTable};
-use crate::asm::model::{AddrMode, AsmStmt, DataPlacement, IndexMode, Instruction, MemRef};
+/// How the value of a relocation's target is stored at its offset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RelocKind {
+    /// 16 bit address or word, little endian
+    Abs16,
+    /// zeropage address or 8 bit value
+    Abs8,
+    /// low byte of a 16 bit value, e.g. `#<msg`
+    LowByte,
+    /// high byte of a 16 bit value, e.g. `#>msg`
+    HighByte,
+    /// branch offset relative to the end of the instruction, which ends
+    /// right after the relocated byte
+    Rel8,
+}
+
+/// Value that can only be filled in once all symbols are known. Every
+/// reference gets its own relocation, even if the target is the same. `loc`
+/// is the statement it belongs to, for reporting errors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Relocation {
+    pub offset: u16,
+    pub kind: RelocKind,
+    pub target: Expr,
+    pub loc: SourceLoc,
+}
+
+/// Source line that emitted the bytes of a blob starting at `offset`, up to
+/// the offset of the next line. `reserved` marks lines that reserve space
+/// with `.res`, whose bytes aren't worth listing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineInfo {
+    pub offset: u16,
+    pub loc: SourceLoc,
+    pub reserved: bool,
+}
 
 pub struct CodeBlob {
     blob: Vec<u8>,
     symbols: SymbolTable,
-    rel8: HashMap<String, u16>,
-    rel16: HashMap<String, u16>,
+    relocations: Vec<Relocation>,
+    errors: Vec<Diagnostic>,
+    // whether there are bytes other than the ones reserved by .res
+    has_contents: bool,
+    lines: Vec<LineInfo>,
+    // whether the section grew beyond 64K
+    overflowed: bool,
+    // statement code is being generated for
+    loc: SourceLoc,
 }
 
-
 impl CodeBlob {
+    /// Blob read back from an object file, see `ObjectFile`.
+    pub fn from_parts(
+        bytes: Vec<u8>,
+        symbols: SymbolTable,
+        relocations: Vec<Relocation>,
+        lines: Vec<LineInfo>,
+        has_contents: bool,
+    ) -> CodeBlob {
+        CodeBlob {
+            blob: bytes,
+            symbols,
+            relocations,
+            lines,
+            has_contents,
+            ..CodeBlob::new()
+        }
+    }
+
     pub fn new() -> CodeBlob {
         CodeBlob {
             blob: vec![],
             symbols: SymbolTable::new(),
-            rel8: HashMap::new(),
-            rel16: HashMap::new(),
+            relocations: vec![],
+            errors: vec![],
+            has_contents: false,
+            lines: vec![],
+            overflowed: false,
+            loc: SourceLoc::default(),
         }
     }
 
@@ -29,84 +96,228 @@ impl CodeBlob {
         &self.symbols
     }
 
-    pub fn dump(&mut self, binary: &mut Vec<u8>) {
-        binary.append(&mut self.blob);
+    pub fn has_contents(&self) -> bool {
+        self.has_contents
+    }
+
+    pub fn errors(&self) -> &Vec<Diagnostic> {
+        &self.errors
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.blob
+    }
+
+    pub fn lines(&self) -> &[LineInfo] {
+        &self.lines
     }
 
-    pub fn resolve_symbols(&mut self, base_addr: u16, global_symbols: &SymbolTable) -> Vec<String> {
+    pub fn relocations(&self) -> &[Relocation] {
+        &self.relocations
+    }
+
+    pub fn resolve_symbols<F>(&mut self, base_addr: u16, lookup: F) -> Vec<Diagnostic>
+    where
+        F: Fn(&str) -> Option<u16>,
+    {
         let mut errors = vec![];
 
-        for (name, offset) in self.rel16.iter() {
-            if let Some(addr) = global_symbols.find(name) {
-                // if the symbol exists, fill in the address
-                let operand = addr.to_le_bytes().to_vec();
-                self.blob[*offset as usize] = operand[0];
-                self.blob[*offset as usize + 1] = operand[1];
-            } else {
-                errors.push(format!("undefined reference to symbol {}", name));
-            }
-        }
+        for reloc in self.relocations.iter() {
+            let value = match reloc.target.eval(&lookup) {
+                Ok(value) => value,
+                Err(error) => {
+                    errors.push(error_at(&reloc.loc, &error.to_string()));
+                    continue;
+                }
+            };
 
-        for (name, offset) in self.rel8.iter() {
-            if let Some(addr) = global_symbols.find(name) {
-                // if the symbol exists, calculate the relative address
-                let delta = addr as i16 - (base_addr + offset + 1) as i16;
-                if delta > i8::MAX as i16 || delta < i8::MIN as i16 {
-                    errors.push(format!("cannot always branch to symbol {}, distance too far", name));
+            let offset = reloc.offset as usize;
+            let result = match reloc.kind {
+                RelocKind::Abs16 => {
+                    let bytes = (value as u16).to_le_bytes();
+                    self.blob[offset..offset + 2].copy_from_slice(&bytes);
+                    check_range(&reloc.target, value, -0x8000..=0xffff, 16)
                 }
-                self.blob[*offset as usize] = delta as u8;
-            } else {
-                errors.push(format!("undefined reference to symbol {}", name));
+                RelocKind::Abs8 => {
+                    // zeropage operands and immediates must not exceed 8 bits
+                    self.blob[offset] = value as u8;
+                    check_range(&reloc.target, value, -0x80..=0xff, 8)
+                }
+                RelocKind::LowByte | RelocKind::HighByte => {
+                    let bytes = (value as u16).to_le_bytes();
+                    self.blob[offset] = match reloc.kind {
+                        RelocKind::HighByte => bytes[1],
+                        _ => bytes[0],
+                    };
+                    check_range(&reloc.target, value, -0x8000..=0xffff, 16)
+                }
+                RelocKind::Rel8 => {
+                    // the branch offset is the last byte of the instruction,
+                    // the distance is relative to the end of the instruction
+                    let pc = base_addr as i64 + offset as i64 + 1;
+                    branch_delta(&reloc.target, value, pc).map(|delta| self.blob[offset] = delta)
+                }
+            };
+            if let Err(message) = result {
+                errors.push(error_at(&reloc.loc, &message));
             }
         }
 
         errors
     }
 
-    pub fn gen_stmt<F>(&mut self, stmt: &AsmStmt, symbol_lookup: F)
+    fn relocate(&mut self, offset: usize, kind: RelocKind, target: Expr) {
+        self.relocations.push(Relocation {
+            offset: offset as u16,
+            kind,
+            target,
+            loc: self.loc.clone(),
+        });
+    }
+
+    fn error(&mut self, message: &str) {
+        self.errors.push(error_at(&self.loc, message));
+    }
+
+    fn check_range(
+        &mut self,
+        expr: &Expr,
+        value: i64,
+        range: std::ops::RangeInclusive<i64>,
+        bits: usize,
+    ) {
+        if let Err(message) = check_range(expr, value, range, bits) {
+            self.error(&message);
+        }
+    }
+
+    pub fn gen_stmt<F>(&mut self, stmt: &SourceStmt, symbol_lookup: F)
     where
         F: Fn(&str) -> Option<u16>,
     {
-        match stmt {
-            AsmStmt::AsmInstruction(instr) => self.gen_instruction(instr, symbol_lookup),
-            AsmStmt::Data(data) => self.gen_data(data),
+        if !matches!(stmt.stmt, AsmStmt::ConstLabel(..)) {
+            self.record_line(stmt);
+        }
+        self.loc = stmt.loc.clone();
+
+        let start = self.blob.len();
+        match &stmt.stmt {
+            AsmStmt::AsmInstruction(instr) => {
+                self.has_contents = true;
+                self.gen_instruction(instr, symbol_lookup)
+            }
+            AsmStmt::Data(data) => {
+                if !matches!(data, DataPlacement::Reserve(_)) {
+                    self.has_contents = true;
+                }
+                self.gen_data(data, symbol_lookup)
+            }
             AsmStmt::Label(name) => self.insert_label(name),
-            _ => {}
+            _ => return,
+        }
+
+        // offsets within the section are 16 bits, anything placed at or
+        // reaching past $10000 doesn't fit. Report it only once.
+        let overflows = start > 0xffff || self.blob.len() > 0x10000;
+        if overflows && !self.overflowed {
+            self.overflowed = true;
+            self.error("section grows beyond 64K");
         }
     }
 
+    fn record_line(&mut self, stmt: &SourceStmt) {
+        // statements on the same line share a single entry
+        let reserved = matches!(stmt.stmt, AsmStmt::Data(DataPlacement::Reserve(_)));
+        if let Some(last) = self.lines.last_mut() {
+            if last.loc.file == stmt.loc.file && last.loc.line == stmt.loc.line {
+                last.reserved |= reserved;
+                return;
+            }
+        }
+        self.lines.push(LineInfo {
+            offset: self.blob.len() as u16,
+            loc: stmt.loc.clone(),
+            reserved,
+        });
+    }
+
     pub fn insert_label(&mut self, name: &str) {
-        let current_addr = self.blob.len();
-        assert!(current_addr <= 0xffff);
-        self.symbols.insert(name, current_addr as u16);
+        // an offset beyond 16 bits is reported by gen_stmt
+        self.symbols.insert(name, self.blob.len() as u16);
+    }
+
+    fn reserve<F>(&mut self, size: &Expr, lookup: &F)
+    where
+        F: Fn(&str) -> Option<u16>,
+    {
+        // the size has to be known right away, it determines the addresses
+        // of all labels that follow
+        let size = match size.eval(lookup) {
+            Ok(value) if (0..=0xffff).contains(&value) => value as usize,
+            Ok(value) => {
+                self.error(&format!(".res {}: size {} out of range", size, value));
+                return;
+            }
+            Err(error) => {
+                self.error(&format!(".res {}: {}", size, error));
+                return;
+            }
+        };
+        self.blob.resize(self.blob.len() + size, 0x00);
     }
 
-    pub fn gen_data(&mut self, data: &DataPlacement) {
+    pub fn gen_data<F>(&mut self, data: &DataPlacement, lookup: F)
+    where
+        F: Fn(&str) -> Option<u16>,
+    {
         match data {
-            DataPlacement::Str(string) => {
-                let mut bytes = string.clone().into_bytes();
-                bytes.push(0x00);
-                self.blob.append(&mut bytes);
-            },
-            DataPlacement::Word(mem_ref) => {
-                let mut data = self.vec_from_mem_ref(mem_ref, false);
-                self.blob.append(&mut data);
-            },
+            DataPlacement::Str(bytes) => {
+                self.blob.extend_from_slice(bytes);
+                self.blob.push(0x00);
+            }
+            DataPlacement::Ascii(bytes) => self.blob.extend_from_slice(bytes),
+            DataPlacement::PStr(bytes) => {
+                self.blob.push(bytes.len() as u8);
+                self.blob.extend_from_slice(bytes);
+            }
+            DataPlacement::Bytes(exprs) => {
+                for expr in exprs.iter() {
+                    let byte = self.byte_operand(expr.clone(), self.blob.len(), &lookup);
+                    self.blob.push(byte);
+                }
+            }
+            DataPlacement::Words(exprs) => {
+                for expr in exprs.iter() {
+                    let word = match self.try_eval(expr, &lookup) {
+                        Some(value) => {
+                            self.check_range(expr, value, -0x8000..=0xffff, 16);
+                            value as u16
+                        }
+                        None => {
+                            self.relocate(self.blob.len(), RelocKind::Abs16, expr.clone());
+                            0
+                        }
+                    };
+                    self.blob.extend_from_slice(&word.to_le_bytes());
+                }
+            }
+            DataPlacement::Reserve(size) => self.reserve(size, &lookup),
         }
     }
 
-    fn vec_from_mem_ref(&mut self, mem_ref: &MemRef, allow_zp: bool) -> Vec<u8> {
-        match mem_ref {
-            MemRef::Addr(addr) => {
-                if allow_zp {
-                    vec! [ *addr as u8 ]
-                } else {
-                    addr.to_le_bytes().to_vec()
-                }
-            },
-            MemRef::Variable(name) => {
-                self.rel16.insert(name.clone(), self.blob.len() as u16);
-                vec![ 0, 0 ]
+    /// Evaluates an expression with the symbols known at code generation time.
+    /// Returns None if the expression refers to symbols that have to be resolved
+    /// at link time.
+    fn try_eval<F>(&mut self, expr: &Expr, lookup: &F) -> Option<i64>
+    where
+        F: Fn(&str) -> Option<u16>,
+    {
+        match expr.eval(lookup) {
+            Ok(value) => Some(value),
+            Err(EvalError::UndefinedSymbol(_)) => None,
+            Err(error) => {
+                self.error(&format!("{}: {}", expr, error));
+                Some(0)
             }
         }
     }
@@ -118,49 +329,180 @@ impl CodeBlob {
         let mnemonic_i = instruction.mnemonic_index();
         let (addr_mode_i, ref mut operand) = match instruction.addr_mode() {
             AddrMode::Implied => (0, vec![]),
-            AddrMode::Immediate(addr) => (1, vec![addr]),
-            AddrMode::Memory(mode, mem_ref) => {
-                let addr = match mem_ref {
-                    MemRef::Addr(addr) => Some(addr),
-                    MemRef::Variable(name) => {
-                        let addr = lookup(&name);
-                        if addr.is_none() {
-                            let rel_addr = (self.blob.len() + 1) as u16;
-                            if instruction.has_rel_addressing() {
-                                self.rel8.insert(name, rel_addr);
-                                Some(0xff)
-                            } else {
-                                self.rel16.insert(name, rel_addr);
-                                Some(0xffff)
-                            }
-                        } else {
-                            addr
-                        }
+            AddrMode::Immediate(expr) => (
+                1,
+                vec![self.byte_operand(expr, self.blob.len() + 1, &lookup)],
+            ),
+            AddrMode::Memory(_, expr) if instruction.has_rel_addressing() => {
+                // branch targets are always resolved at link time since
+                // their final address isn't known yet.
+                self.relocate(self.blob.len() + 1, RelocKind::Rel8, expr);
+                (13, vec![0])
+            }
+            AddrMode::Memory(mode, expr) => self.mem_operand(mnemonic_i, mode, expr, lookup),
+            AddrMode::Indirect(mode, expr) => self.indirect_operand(mnemonic_i, mode, expr, lookup),
+            AddrMode::BitBranch(zp_expr, target) => {
+                let zp_addr = self.byte_operand(zp_expr, self.blob.len() + 1, &lookup);
+                self.relocate(self.blob.len() + 2, RelocKind::Rel8, target);
+                (13, vec![zp_addr, 0])
+            }
+        };
+
+        if let Some(opcode) = get_opcode(mnemonic_i, addr_mode_i) {
+            self.blob.push(opcode);
+            self.blob.append(operand);
+        } else {
+            self.error(&format!("invalid addr mode {:?}", instruction.addr_mode()));
+        }
+    }
+
+    fn byte_operand<F>(&mut self, expr: Expr, offset: usize, lookup: &F) -> u8
+    where
+        F: Fn(&str) -> Option<u16>,
+    {
+        // 8 bit value at the given blob offset
+        match self.try_eval(&expr, lookup) {
+            Some(value) => {
+                self.check_range(&expr, value, -0x80..=0xff, 8);
+                value as u8
+            }
+            None => {
+                match expr {
+                    Expr::Unary(UnaryOp::LowByte, operand) => {
+                        self.relocate(offset, RelocKind::LowByte, *operand)
                     }
+                    Expr::Unary(UnaryOp::HighByte, operand) => {
+                        self.relocate(offset, RelocKind::HighByte, *operand)
+                    }
+                    _ => self.relocate(offset, RelocKind::Abs8, expr),
                 };
+                0
+            }
+        }
+    }
 
-                if addr.is_some() && addr.unwrap() < 256 {
-                    let addr = addr.unwrap();
-                    match mode {
-                        IndexMode::None => (2, vec![addr as u8]),
-                        IndexMode::IndexedX => (3, vec![addr as u8]),
-                        IndexMode::IndexedY => (4, vec![addr as u8]),
-                    }
+    fn mem_operand<F>(
+        &mut self,
+        mnemonic_i: usize,
+        mode: IndexMode,
+        expr: Expr,
+        lookup: F,
+    ) -> (usize, Vec<u8>)
+    where
+        F: Fn(&str) -> Option<u16>,
+    {
+        let (zp_mode_i, abs_mode_i) = match mode {
+            IndexMode::None => (2, 8),
+            IndexMode::IndexedX => (3, 9),
+            IndexMode::IndexedY => (4, 10),
+        };
+        let has_abs_mode = get_opcode(mnemonic_i, abs_mode_i).is_some();
+
+        match self.try_eval(&expr, &lookup) {
+            Some(addr) => {
+                self.check_range(&expr, addr, 0..=0xffff, 16);
+                if addr < 256 && get_opcode(mnemonic_i, zp_mode_i).is_some() {
+                    (zp_mode_i, vec![addr as u8])
+                } else if has_abs_mode {
+                    (abs_mode_i, (addr as u16).to_le_bytes().to_vec())
                 } else {
-                    match mode {
-                        IndexMode::None => (8, vec![ 0, 0 ]),
-                        IndexMode::IndexedX => (9, vec![ 0, 0 ]),
-                        IndexMode::IndexedY => (10, vec![ 0, 0 ]),
+                    // no absolute form to fall back to
+                    if (0x100..=0xffff).contains(&addr) {
+                        self.error(&format!("address ${:04x} does not fit into 8 bits", addr));
                     }
+                    (zp_mode_i, vec![addr as u8])
+                }
+            }
+            None => {
+                let offset = self.blob.len() + 1;
+                if has_abs_mode {
+                    self.relocate(offset, RelocKind::Abs16, expr);
+                    (abs_mode_i, vec![0, 0])
+                } else {
+                    // instructions like RMBn/SMBn or STX zp,Y only
+                    // exist with a zeropage operand
+                    self.relocate(offset, RelocKind::Abs8, expr);
+                    (zp_mode_i, vec![0])
                 }
             }
+        }
+    }
+
+    fn indirect_operand<F>(
+        &mut self,
+        mnemonic_i: usize,
+        mode: IndexMode,
+        expr: Expr,
+        lookup: F,
+    ) -> (usize, Vec<u8>)
+    where
+        F: Fn(&str) -> Option<u16>,
+    {
+        // (zp), (zp,X) and (zp),Y only take a zeropage pointer, while the
+        // 16 bit (abs) and (abs,X) forms are only available for JMP.
+        let (zp_mode_i, abs_mode_i) = match mode {
+            IndexMode::None => (5, Some(11)),
+            IndexMode::IndexedX => (6, Some(12)),
+            IndexMode::IndexedY => (7, None),
         };
+        let abs_mode_i = abs_mode_i.filter(|i| get_opcode(mnemonic_i, *i).is_some());
 
-        if let Some(opcode) = get_opcode(mnemonic_i, addr_mode_i) {
-            self.blob.push(opcode);
-            self.blob.append(operand);
-        } else {
-            println!("invalid addr mode {:?}", instruction.addr_mode());
+        let addr = match self.try_eval(&expr, &lookup) {
+            Some(addr) => addr,
+            None => {
+                // unresolved symbols use the zeropage form unless the
+                // instruction only supports a 16 bit pointer.
+                let offset = self.blob.len() + 1;
+                return match abs_mode_i {
+                    Some(abs_mode_i) => {
+                        self.relocate(offset, RelocKind::Abs16, expr);
+                        (abs_mode_i, vec![0, 0])
+                    }
+                    None => {
+                        self.relocate(offset, RelocKind::Abs8, expr);
+                        (zp_mode_i, vec![0])
+                    }
+                };
+            }
+        };
+
+        match abs_mode_i {
+            Some(abs_mode_i) if addr > 0xff || get_opcode(mnemonic_i, zp_mode_i).is_none() => {
+                self.check_range(&expr, addr, 0..=0xffff, 16);
+                (abs_mode_i, (addr as u16).to_le_bytes().to_vec())
+            }
+            _ => {
+                self.check_range(&expr, addr, 0..=0xff, 8);
+                (zp_mode_i, vec![addr as u8])
+            }
         }
     }
 }
+
+fn check_range(
+    expr: &Expr,
+    value: i64,
+    range: std::ops::RangeInclusive<i64>,
+    bits: usize,
+) -> Result<(), String> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(format!(
+            "value of {} ({}) does not fit into {} bits",
+            expr, value, bits
+        ))
+    }
+}
+
+fn branch_delta(target_expr: &Expr, target: i64, pc: i64) -> Result<u8, String> {
+    let delta = target - pc;
+    if delta > i8::MAX as i64 || delta < i8::MIN as i64 {
+        Err(format!(
+            "cannot always branch to {}, distance too far",
+            target_expr
+        ))
+    } else {
+        Ok(delta as u8)
+    }
+}
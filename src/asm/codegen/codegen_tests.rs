@@ -0,0 +1,1188 @@
+use super::{symfile, Archive, CodeGenerator, Linker, ObjectFile, SymbolFormat};
+use crate::{
+    asm::{
+        ldscript::{self, LdScript, LdSection},
+        AsmParser,
+    },
+    errors::Diagnostics,
+};
+
+fn messages(errors: Diagnostics) -> Vec<String> {
+    errors
+        .iter()
+        .map(|error| error.message().to_string())
+        .collect()
+}
+
+fn assemble(source: &str) -> Result<Vec<u8>, Vec<String>> {
+    let mut codegen = CodeGenerator::new();
+    let mut parser = AsmParser::new(source);
+    parser.parse(&mut codegen);
+    assert!(parser.diagnostics("").is_empty());
+
+    codegen
+        .link(LdScript::new(vec![LdSection::new("text", Some(0x8000))]))
+        .map(|image| image.to_binary())
+        .map_err(messages)
+}
+
+#[test]
+fn indirect_zeropage_modes() {
+    let binary = assemble(
+        r#"
+        ptr = $20
+        lda (ptr),y
+        sta (ptr,x)
+        cmp ($fe)
+        adc (r3),y
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(binary, vec![0xb1, 0x20, 0x81, 0x20, 0xd2, 0xfe, 0x71, 0x03]);
+}
+
+#[test]
+fn indirect_jmp_modes() {
+    let binary = assemble(
+        r#"
+        jmp (vector)
+        jmp (table,x)
+        jmp ($12)
+    vector:
+        .word handler
+    table:
+    handler:
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        binary,
+        vec![0x6c, 0x09, 0x80, 0x7c, 0x0b, 0x80, 0x6c, 0x12, 0x00, 0x0b, 0x80]
+    );
+}
+
+#[test]
+fn indirect_zeropage_relocation() {
+    let mut codegen = CodeGenerator::new();
+    let mut parser = AsmParser::new(
+        r#"
+        lda (ptr),y
+        sta (ptr2,x)
+    ptr:
+    ptr2:
+    "#,
+    );
+    parser.parse(&mut codegen);
+    let binary = codegen
+        .link(LdScript::new(vec![LdSection::new("text", Some(0x0010))]))
+        .map(|image| image.to_binary());
+    assert_eq!(binary, Ok(vec![0xb1, 0x14, 0x81, 0x14]));
+
+    // outside of the zeropage, the pointer cannot be encoded
+    let binary = assemble(
+        r#"
+        lda (ptr),y
+    ptr:
+    "#,
+    );
+    assert!(binary.is_err());
+}
+
+#[test]
+fn absolute_operands() {
+    let binary = assemble(
+        r#"
+        lda $1234
+        stx 0x8000
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(binary, vec![0xad, 0x34, 0x12, 0x8e, 0x00, 0x80]);
+}
+
+#[test]
+fn bit_manipulation_instructions() {
+    let binary = assemble(
+        r#"
+        flags = $12
+    loop:
+        rmb0 flags
+        smb7 $fe
+        bbr3 $20, skip
+        inx
+    skip:
+        bbs7 flags, loop
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        binary,
+        vec![0x07, 0x12, 0xf7, 0xfe, 0x3f, 0x20, 0x01, 0xe8, 0xff, 0x12, 0xf5]
+    );
+}
+
+#[test]
+fn bit_manipulation_relocation() {
+    let mut codegen = CodeGenerator::new();
+    let mut parser = AsmParser::new(
+        r#"
+        smb2 flags
+        bbs2 flags2, done
+    flags:
+    flags2:
+    done:
+    "#,
+    );
+    parser.parse(&mut codegen);
+    let binary = codegen
+        .link(LdScript::new(vec![LdSection::new("text", Some(0x0010))]))
+        .map(|image| image.to_binary());
+    assert_eq!(binary, Ok(vec![0xa7, 0x15, 0xaf, 0x15, 0x00]));
+}
+
+#[test]
+fn zeropage_only_operands() {
+    // zp,Y only exists for LDX/STX and RMBn/SMBn only take a zeropage
+    // operand, so larger addresses can't fall back to absolute addressing
+    assert_eq!(
+        assemble("stx $12,y\nsmb3 $fe").unwrap(),
+        vec![0x96, 0x12, 0xb7, 0xfe]
+    );
+    assert_eq!(
+        assemble("stx $1234,y").unwrap_err(),
+        vec!["address $1234 does not fit into 8 bits"]
+    );
+    assert_eq!(
+        assemble("rmb0 $1234").unwrap_err(),
+        vec!["address $1234 does not fit into 8 bits"]
+    );
+}
+
+#[test]
+fn constant_expressions() {
+    let binary = assemble(
+        r#"
+        BUF_SIZE = 16
+        ptr = BUF + 2
+        BUF = $20
+        ldx #BUF_SIZE-1
+        lda ptr
+        lda #-1
+        ora #(1 << 7) | 3
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(binary, vec![0xa2, 0x0f, 0xa5, 0x22, 0xa9, 0xff, 0x09, 0x83]);
+}
+
+#[test]
+fn link_time_expressions() {
+    let binary = assemble(
+        r#"
+        end = table + 4
+        lda table+1,x
+        .word handler+2
+        .word end
+        .word handler-table
+    table:
+        rts
+    handler:
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        binary,
+        vec![0xbd, 0x0a, 0x80, 0x0c, 0x80, 0x0d, 0x80, 0x01, 0x00, 0x60]
+    );
+}
+
+#[test]
+fn expression_errors() {
+    // out of range results, division by zero and undefined symbols
+    for source in [
+        "lda #table\ntable:",
+        ".word 1/(2-2)",
+        ".word 1/0",
+        "lda table*4\ntable = $4000",
+        ".word missing",
+        "end = missing + 1",
+        "bne far_away\nfar_away = $9000",
+    ] {
+        assert!(assemble(source).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn low_high_byte_operators() {
+    let binary = assemble(
+        r#"
+        vector = $1234
+        lda #<msg
+        sta r0
+        lda #>msg
+        sta r1
+        ldx #lo(vector)
+        ldy #hi(vector + $100)
+        lda #>msg + 1
+    msg:
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        binary,
+        vec![0xa9, 0x0e, 0x85, 0x00, 0xa9, 0x80, 0x85, 0x01, 0xa2, 0x34, 0xa0, 0x13, 0xa9, 0x81,]
+    );
+}
+
+#[test]
+fn data_tables() {
+    let binary = assemble(
+        r#"
+        .byte 1, 2, $ff, -1
+        .byte <handler, >handler, handler - table + 7
+        .word handler, table, $1234
+    table:
+    handler:
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        binary,
+        vec![0x01, 0x02, 0xff, 0xff, 0x0d, 0x80, 0x07, 0x0d, 0x80, 0x0d, 0x80, 0x34, 0x12]
+    );
+}
+
+#[test]
+fn string_data() {
+    let binary = assemble(
+        r#"
+        .str "A\n"
+        .ascii "B\t"
+        .pstr "C\x7f"
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(binary, vec![0x41, 0x0a, 0x00, 0x42, 0x09, 0x02, 0x43, 0x7f]);
+}
+
+fn link_script(source: &str, ldscript: &str) -> Result<Vec<(u16, Vec<u8>)>, Vec<String>> {
+    let mut codegen = CodeGenerator::new();
+    let mut parser = AsmParser::new(source);
+    parser.parse(&mut codegen);
+    assert!(parser.diagnostics("").is_empty());
+
+    let script = ldscript::parse(ldscript).unwrap();
+    codegen
+        .link(script)
+        .map(|image| {
+            image
+                .segments()
+                .iter()
+                .map(|segment| (segment.addr(), segment.data().to_vec()))
+                .collect()
+        })
+        .map_err(messages)
+}
+
+#[test]
+fn memory_regions() {
+    let segments = link_script(
+        r#"
+        jmp start
+        section data
+    counter:
+        .byte 0
+        section more_text
+    start:
+        inc counter
+    "#,
+        r#"
+        ROM: start=$e000 size=$2000
+        RAM: start=$0200 size=$7e00
+        .text > ROM
+        .data > RAM
+        .more_text > ROM
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        segments,
+        vec![
+            (0xe000, vec![0x4c, 0x03, 0xe0]),
+            (0x0200, vec![0x00]),
+            (0xe003, vec![0xee, 0x00, 0x02]),
+        ]
+    );
+}
+
+#[test]
+fn memory_region_overflow() {
+    let errors = link_script(
+        ".word 1\nsection data\n.byte 1, 2, 3\n",
+        "ROM: start=$e000 size=4\n.text > ROM\n.data > ROM\n",
+    )
+    .unwrap_err();
+    assert_eq!(
+        errors,
+        vec!["section .data ($e002-$e004) does not fit into region ROM ($e000-$e003)"]
+    );
+
+    // an explicit address outside of the region is an error, too
+    let errors =
+        link_script(".byte 1\n", "ROM: start=$e000 size=4\n.text @$d000 > ROM\n").unwrap_err();
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn overlapping_sections() {
+    let errors = link_script(
+        ".word 1, 2\nsection data\n.byte 1\nsection bss\n",
+        ".text @$8000\n.data @$8003\n.bss @$8002\n",
+    )
+    .unwrap_err();
+    assert_eq!(
+        errors,
+        vec!["sections .text ($8000-$8003) and .data ($8003-$8003) overlap"]
+    );
+}
+
+#[test]
+fn address_space_overflow() {
+    let errors =
+        link_script(".word 1\nsection data\n.byte 1\n", ".text @$fffe\n.data\n").unwrap_err();
+    assert_eq!(
+        errors,
+        vec!["section .data (size 1 at $10000) extends beyond the address space"]
+    );
+
+    let segments = link_script(".word $1234\n", ".text @$fffe\n").unwrap();
+    assert_eq!(segments, vec![(0xfffe, vec![0x34, 0x12])]);
+    // sections that are too large on their own
+    let errors = link_script(".res $ffff\n.byte 1\nx: nop\n", ".text @0\n").unwrap_err();
+    assert_eq!(errors, vec!["section grows beyond 64K"]);
+    let errors = link_script(".res $ffff\n.byte 1\n", ".text @1\n").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].ends_with("extends beyond the address space"));
+}
+
+#[test]
+fn unplaced_sections() {
+    let errors = link_script(".byte 1\nsection extra\n.byte 2\n", ".text @$8000\n").unwrap_err();
+    assert_eq!(
+        errors,
+        vec!["section .extra is not placed by the linker script"]
+    );
+}
+
+#[test]
+fn separate_load_addresses() {
+    let segments = link_script(
+        r#"
+        lda #<__data_load_start
+        ldx #>__data_load_start
+        ldy #__data_size
+        lda __data_run_start
+        lda counter
+        section data
+        .byte 1
+    counter:
+        .byte 2
+    "#,
+        r#"
+        ROM: start=$e000 size=$2000
+        RAM: start=$0200 size=$7e00
+        .text > ROM
+        .data > RAM AT > ROM
+        .rodata > ROM
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        segments,
+        vec![
+            (
+                0xe000,
+                vec![0xa9, 0x0c, 0xa2, 0xe0, 0xa0, 0x02, 0xad, 0x00, 0x02, 0xad, 0x01, 0x02]
+            ),
+            (0xe00c, vec![0x01, 0x02]),
+        ]
+    );
+}
+
+#[test]
+fn overlapping_load_addresses() {
+    let errors = link_script(
+        ".byte 1, 2\nsection data\n.byte 3\n",
+        ".text @$e000\n.data @$0200 AT @$e001\n",
+    )
+    .unwrap_err();
+    assert_eq!(
+        errors,
+        vec!["sections .text ($e000-$e001) and .data ($e001-$e001) overlap"]
+    );
+
+    let errors = link_script(
+        ".byte 1\nsection data\n.byte 2, 3\n",
+        "ROM: start=$e000 size=2\n.text > ROM\n.data @$0200 AT > ROM\n",
+    )
+    .unwrap_err();
+    assert_eq!(
+        errors,
+        vec![
+            "load image of section .data ($e001-$e002) does not fit into region ROM ($e000-$e001)"
+        ]
+    );
+}
+
+#[test]
+fn section_boundary_symbols() {
+    let segments = link_script(
+        r#"
+        heap_start = __bss_end
+        .word __text_start, __text_end, __data_start, __data_end, __data_size
+        .word __bss_start, __bss_size, heap_start, __vectors_end
+        section data
+        .byte 1, 2, 3
+        section vectors
+        .word 0
+    "#,
+        r#"
+        .text @$8000
+        .data
+        .bss @$0200
+        .vectors @$fffe
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        segments[0],
+        (
+            0x8000,
+            vec![
+                0x00, 0x80, 0x12, 0x80, 0x12, 0x80, 0x15, 0x80, 0x03, 0x00, 0x00, 0x02, 0x00, 0x00,
+                0x00, 0x02, 0x00, 0x00
+            ]
+        )
+    );
+}
+
+#[test]
+fn reserved_space() {
+    let source = r#"
+        SIZE = 3
+        .byte 1
+        .res SIZE
+    after:
+        .word after
+        section bss
+    buffer:
+        .res 256
+    flags:
+        .res 1
+        section text
+        .word buffer, flags, __bss_end
+    "#;
+    let segments = link_script(
+        source,
+        r#"
+        ROM: start=$e000 size=$2000
+        RAM: start=$0200 size=$0200
+        .text > ROM
+        .bss > RAM NOLOAD
+    "#,
+    )
+    .unwrap();
+
+    // reserved bytes are zero filled in regular sections, NOLOAD sections
+    // don't show up in the image at all
+    assert_eq!(
+        segments,
+        vec![(
+            0xe000,
+            vec![0x01, 0x00, 0x00, 0x00, 0x04, 0xe0, 0x00, 0x02, 0x00, 0x03, 0x01, 0x03]
+        )]
+    );
+
+    let errors = link_script(
+        "section bss\n.res 2\n.byte 1\n",
+        ".text @$e000\n.bss @$0200 NOLOAD\n",
+    )
+    .unwrap_err();
+    assert_eq!(
+        errors,
+        vec!["section .bss is NOLOAD but contains code or data".to_string()]
+    );
+
+    let errors = link_script(".res $ffff\n.res 2\n", ".text @0\n").unwrap_err();
+    assert_eq!(errors, vec!["section grows beyond 64K".to_string()]);
+}
+
+#[test]
+fn section_size_limit() {
+    // a section may fill all of the 64K, but nothing can follow
+    let segments = link_script(".res $ffff\n.byte 1\n", ".text @0\n").unwrap();
+    assert_eq!(segments[0].1.len(), 0x10000);
+
+    for source in [
+        ".res $ffff\n.byte 1\nx: nop\n",
+        ".res $ffff\n.byte 1\nend:\n",
+        ".res $fffe\n.word 1, 2\n",
+        ".res $ffff\nlda $1234\n.res 2\n.byte 3\n",
+    ] {
+        let errors = link_script(source, ".text @0\n").unwrap_err();
+        assert_eq!(errors.len(), 1, "{}", source);
+        assert!(
+            errors[0].starts_with("section grows beyond 64K"),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn listing() {
+    let mut codegen = CodeGenerator::new();
+    let mut parser = AsmParser::new(
+        "LEN = 2\nstart: lda #LEN; jmp start\n.str \"Hello\"\nsection bss\nbuf: .res 16\n",
+    );
+    parser.set_file_name("main.s");
+    parser.parse(&mut codegen);
+    assert!(parser.diagnostics("").is_empty());
+
+    let script = ldscript::parse(".text @$e000\n.bss @$0200 NOLOAD\n").unwrap();
+    codegen.link(script).unwrap();
+    let listing = codegen.listing();
+
+    let expected_lines = "\
+section .text $e000-$e00a (11 bytes)
+main.s
+e000  a9 02 4c 00      2  start: lda #LEN; jmp start
+e004  e0
+e005  48 65 6c 6c      3  .str \"Hello\"
+e009  6f 00
+
+section .bss $0200-$020f (16 bytes)
+main.s
+0200                   5  buf: .res 16
+
+symbols
+LEN                $0002
+";
+    assert!(listing.starts_with(expected_lines), "{}", listing);
+    assert!(listing.ends_with("buf                $0200\nstart              $e000\n"));
+}
+
+#[test]
+fn map_file() {
+    let mut codegen = CodeGenerator::new();
+    let mut parser = AsmParser::new(
+        "start: lda counter\nsection data\ncounter: .byte 1, 2\nsection bss\n.res 16\n",
+    );
+    parser.parse(&mut codegen);
+    assert!(parser.diagnostics("").is_empty());
+
+    let script = ldscript::parse(
+        r#"
+        ROM: start=$e000 size=$2000
+        RAM: start=$0200 size=$0600
+        .text > ROM
+        .data > RAM AT > ROM
+        .bss > RAM NOLOAD
+    "#,
+    )
+    .unwrap();
+    codegen.link(script).unwrap();
+    let map = codegen.map();
+
+    let expected_lines = "\
+section  start  end    size   load
+.text    $e000  $e002      3
+.data    $0200  $0201      2  $e003
+.bss     $0202  $0211     16  NOLOAD
+
+region  start  end    size   used   free
+ROM     $e000  $ffff   8192      5   8187
+RAM     $0200  $07ff   1536     18   1518
+
+symbols by name
+";
+    assert!(map.starts_with(expected_lines), "{}", map);
+    assert!(map.contains("\ncounter            $0200\nstart              $e000\n"));
+    assert!(map.ends_with("$e000  start\n$e003  __data_load_start\n$e003  __text_end\n"));
+}
+
+#[test]
+fn symbol_export() {
+    let mut codegen = CodeGenerator::new();
+    let mut parser = AsmParser::new("ACIA = $8000\nreset: lda ACIA\n");
+    parser.parse(&mut codegen);
+    assert!(parser.diagnostics("").is_empty());
+    codegen
+        .link(LdScript::new(vec![LdSection::new("text", Some(0xe000))]))
+        .unwrap();
+
+    let vice = codegen.export_symbols(SymbolFormat::Vice);
+    assert!(vice.starts_with("al C:8000 .ACIA\nal C:e003 .__text_end\n"));
+    assert!(vice.ends_with("al C:e000 .reset\n"));
+
+    let text = codegen.export_symbols(SymbolFormat::Text);
+    assert!(text.starts_with("ACIA = $8000\n__text_end = $e003\n"));
+    assert!(text.ends_with("reset = $e000\n"));
+
+    let json = codegen.export_symbols(SymbolFormat::Json);
+    assert!(json.starts_with(
+        "{\n  \"symbols\": [\n    {\"name\": \"ACIA\", \"value\": 32768},\n    {\"name\": "
+    ));
+    assert!(json.ends_with(",\n    {\"name\": \"reset\", \"value\": 57344}\n  ]\n}\n"));
+    assert_eq!(
+        symfile::write(&[], SymbolFormat::Json),
+        "{\n  \"symbols\": [\n  ]\n}\n"
+    );
+}
+
+#[test]
+fn symbol_export_skips_ambiguous_locals() {
+    let mut linker = Linker::new();
+    linker.add_object(compile(
+        "main.s",
+        ".global start\nstart: jsr wait\nloop: bra loop\n",
+    ));
+    linker.add_object(compile(
+        "wait.s",
+        ".global wait\nwait: ldx #0\nloop: dex\nbne loop\nrts\nstart = 1\n",
+    ));
+    linker.add_object(compile(
+        "led.s",
+        ".global led_on\nled_on: rts\nwaiting: rts\n",
+    ));
+    linker
+        .link(ldscript::parse(".text @$e000\n").unwrap())
+        .unwrap();
+
+    assert_eq!(
+        linker.export_symbols(SymbolFormat::Text),
+        "__text_end = $e00d\n__text_load_start = $e000\n__text_run_start = $e000\n\
+         __text_size = $000d\n__text_start = $e000\nled_on = $e00b\nstart = $e000\n\
+         wait = $e005\nwaiting = $e00c\n"
+    );
+    // the map still shows all of them
+    assert!(linker
+        .map()
+        .contains("\nloop               $e003\nloop               $e007\n"));
+}
+
+#[test]
+fn repeated_forward_references() {
+    let binary = assemble(
+        r#"
+        jsr putc
+        jsr putc
+        bne done
+        bne done
+        lda #<msg
+        ldx #<msg
+        bbr0 flags, done
+        bbr1 flags, done
+        .word msg, msg
+    putc:
+        rts
+    done:
+        rts
+    msg:
+        .byte 0
+        flags = $12
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        binary,
+        vec![
+            0x20, 0x18, 0x80, // jsr putc
+            0x20, 0x18, 0x80, // jsr putc
+            0xd0, 0x11, // bne done
+            0xd0, 0x0f, // bne done
+            0xa9, 0x1a, // lda #<msg
+            0xa2, 0x1a, // ldx #<msg
+            0x0f, 0x12, 0x08, // bbr0 flags, done
+            0x1f, 0x12, 0x05, // bbr1 flags, done
+            0x1a, 0x80, 0x1a, 0x80, // .word msg, msg
+            0x60, 0x60, 0x00,
+        ]
+    );
+}
+
+#[test]
+fn repeated_relocation_errors() {
+    // every reference is checked, not just one of them
+    let errors = assemble(
+        r#"
+        lda #far
+        lda #far
+        jmp nowhere
+        jmp nowhere
+        far = $1234 + later
+    later:
+    "#,
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        errors,
+        vec![
+            "value of far (37438) does not fit into 8 bits".to_string(),
+            "value of far (37438) does not fit into 8 bits".to_string(),
+            "undefined reference to symbol nowhere".to_string(),
+            "undefined reference to symbol nowhere".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn located_errors() {
+    let locations = |errors: &Diagnostics| -> Vec<(Option<String>, Option<u32>, String)> {
+        errors
+            .iter()
+            .map(|e| (e.file().map(String::from), e.line(), e.message().into()))
+            .collect()
+    };
+
+    // found while generating code
+    let mut codegen = CodeGenerator::new();
+    let mut parser = AsmParser::new("big = $1234\nnop\nlda #big\n.res $ffff\n");
+    parser.set_file_name("main.s");
+    parser.parse(&mut codegen);
+    assert_eq!(
+        locations(&codegen.compile().err().unwrap()),
+        vec![
+            (
+                Some("main.s".into()),
+                Some(3),
+                "value of big (4660) does not fit into 8 bits".into()
+            ),
+            (
+                Some("main.s".into()),
+                Some(4),
+                "section grows beyond 64K".into()
+            ),
+        ]
+    );
+
+    // found while linking, the locations are kept in object files
+    let object = compile(
+        "main.s",
+        "nop\njmp nowhere\nlda #later\nlater:\nx = later * 2\n",
+    );
+    let mut linker = Linker::new();
+    linker.add_object(ObjectFile::read(&object.write()).unwrap());
+    let errors = linker
+        .link(ldscript::parse(".text @$e000\n").unwrap())
+        .unwrap_err();
+    assert_eq!(
+        locations(&errors),
+        vec![
+            (
+                Some("main.s".into()),
+                Some(5),
+                "constant x (114700) does not fit into 16 bits".into()
+            ),
+            (
+                Some("main.s".into()),
+                Some(2),
+                "undefined reference to symbol nowhere".into()
+            ),
+            (
+                Some("main.s".into()),
+                Some(3),
+                "value of later (57350) does not fit into 8 bits".into()
+            ),
+        ]
+    );
+
+    // sources without a name only have a line number
+    let mut codegen = CodeGenerator::new();
+    AsmParser::new("nop\nbeq far\n").parse(&mut codegen);
+    let errors = codegen
+        .link(ldscript::parse(".text @0\n").unwrap())
+        .unwrap_err();
+    assert_eq!(
+        errors.to_string(),
+        "line 2: error: undefined reference to symbol far\n"
+    );
+}
+
+#[test]
+fn circular_constants() {
+    let link = |objects: Vec<ObjectFile>| {
+        let mut linker = Linker::new();
+        for object in objects {
+            linker.add_object(object);
+        }
+        let errors = linker
+            .link(ldscript::parse(".text @$e000\n").unwrap())
+            .unwrap_err();
+        errors.to_string()
+    };
+
+    // each cycle is reported once, constants depending on it aren't
+    let main = compile("main.s", "a = b\nb = a\nc = a + 1\nx = x + 1\nd = e\n");
+    assert_eq!(
+        link(vec![main]),
+        "main.s:1: error: circular definition of a\n\
+         main.s:4: error: circular definition of x\n\
+         main.s:5: error: constant d: undefined reference to symbol e\n"
+    );
+
+    // cycles may span objects
+    let a = compile("a.s", ".global a\na = b\n");
+    let b = compile("b.s", ".global b\nb = a + 1\n");
+    assert_eq!(link(vec![a, b]), "a.s:2: error: circular definition of a\n");
+}
+
+fn compile(file: &str, source: &str) -> ObjectFile {
+    let mut codegen = CodeGenerator::new();
+    let mut parser = AsmParser::new(source);
+    parser.set_file_name(file);
+    parser.parse(&mut codegen);
+    assert!(parser.diagnostics("").is_empty());
+    codegen.compile().unwrap()
+}
+
+#[test]
+fn object_file_round_trip() {
+    let object = compile(
+        "main.s",
+        "SIZE = 2\nstart: jsr putc\n lda #<(msg+SIZE*2)\n bbr0 $12, start\n.res SIZE\nsection bss\n.res 4\n",
+    );
+    let text = object.write();
+    assert_eq!(
+        text,
+        "\
+retro-lang object 1
+file 0 main.s
+const SIZE 0 1 2
+section bss reserve 4
+line 0 r 0 7 .res 4
+section text data
+bytes 200000a9000f12000000
+label start 0
+reloc 1 abs16 0 2 putc
+reloc 4 lo8 0 3 msg + (SIZE * 2)
+reloc 7 rel8 0 4 start
+line 0 - 0 2 start: jsr putc
+line 3 - 0 3  lda #<(msg+SIZE*2)
+line 5 - 0 4  bbr0 $12, start
+line 8 r 0 5 .res SIZE
+"
+    );
+    assert_eq!(ObjectFile::read(&text).unwrap().write(), text);
+}
+
+#[test]
+fn object_file_expressions() {
+    // operators that could run into the following token when written
+    // without spaces or parentheses
+    let object = compile(
+        "main.s",
+        ".global x, y, z\ny = x % 10\nz = <(<x) + >-x\nlda #x%%10\n.word >(>x), x-%11, -(-x)\n",
+    );
+    let text = object.write();
+    for expected in [
+        "const y 0 2 x % 10\n",
+        "const z 0 3 <(<x) + >(-x)\n",
+        "reloc 1 abs8 0 4 x % 2\n",
+        "reloc 2 abs16 0 5 >(>x)\n",
+        "reloc 4 abs16 0 5 x - 3\n",
+        "reloc 6 abs16 0 5 -(-x)\n",
+    ] {
+        assert!(text.contains(expected), "{}\n{}", expected, text);
+    }
+    assert_eq!(ObjectFile::read(&text).unwrap().write(), text);
+
+    let mut linker = Linker::new();
+    linker.add_object(ObjectFile::read(&text).unwrap());
+    linker.add_object(compile("x.s", ".global x\nx = $1234\n"));
+    let image = linker.link(ldscript::parse(".text @$e000\n").unwrap());
+    assert_eq!(
+        image.unwrap().to_binary(),
+        vec![0xa9, 0x00, 0x00, 0x00, 0x31, 0x12, 0x34, 0x12]
+    );
+    assert_eq!(linker.find_symbol("y"), Some(0x1234 % 10));
+    assert_eq!(linker.find_symbol("z"), Some(0x34 + 0xed));
+}
+
+#[test]
+fn object_file_sections() {
+    // empty sections are left out, reserved space only stores its size
+    let object = compile("main.s", "section bss\nbuffer: .res $4000\nsection zp\n");
+    assert_eq!(
+        object.write(),
+        "retro-lang object 1\nfile 0 main.s\nsection bss reserve 16384\nlabel buffer 0\n\
+         line 0 r 0 2 buffer: .res $4000\n"
+    );
+    let object = ObjectFile::read(&object.write()).unwrap();
+    let mut linker = Linker::new();
+    linker.add_object(object);
+    linker.add_object(compile("end.s", "section bss\n.global end\nend:\n"));
+    linker
+        .link(ldscript::parse(".text @$e000\n.bss @$0200 NOLOAD\n").unwrap())
+        .unwrap();
+    assert_eq!(linker.find_symbol("end"), Some(0x4200));
+}
+
+#[test]
+fn invalid_object_files() {
+    let read_error = |text: &str| ObjectFile::read(text).err().unwrap();
+
+    assert_eq!(read_error("section text data\n"), "not an object file");
+    let header = "retro-lang object 1\n";
+    assert_eq!(
+        read_error(&format!("{}bytes 00\n", header)),
+        "line 2: bytes record outside of a section"
+    );
+    assert_eq!(
+        read_error(&format!(
+            "{}section text data\nbytes 0000\nreloc 1 abs16 - 1 x\n",
+            header
+        )),
+        "line 4: offset 1 outside of the section"
+    );
+    assert_eq!(
+        read_error(&format!(
+            "{}section text data\nbytes 0000\nreloc 0 abs16 1 1 x\n",
+            header
+        )),
+        "line 4: unknown file 1"
+    );
+    assert_eq!(
+        read_error(&format!("{}const x - 1 1+\n", header)),
+        "line 2: invalid expression '1+'"
+    );
+    assert_eq!(
+        read_error(&format!("{}symbol x 1\n", header)),
+        "line 2: unknown record 'symbol'"
+    );
+    assert_eq!(
+        read_error(&format!("{}section bss reserve 2\nbytes 0000\n", header)),
+        "line 3: bytes record in reserve section .bss"
+    );
+    assert_eq!(
+        read_error(&format!("{}section bss reserve\n", header)),
+        "line 2: invalid section contents 'reserve'"
+    );
+    assert_eq!(
+        read_error(&format!("{}section bss reserve 65537\n", header)),
+        "line 2: section .bss exceeds 64K"
+    );
+}
+
+#[test]
+fn link_object_files() {
+    let main = compile(
+        "main.s",
+        "start: jsr putc\njsr putc\njmp start\nsection data\n.byte COUNT\n",
+    );
+    let lib = compile(
+        "lib.s",
+        "COUNT = 3\nputc: rts\nsection data\n.byte 4\n.global COUNT, putc\n",
+    );
+
+    let mut linker = Linker::new();
+    linker.add_object(ObjectFile::read(&main.write()).unwrap());
+    linker.add_object(ObjectFile::read(&lib.write()).unwrap());
+    let script = ldscript::parse(".text @$e000\n.data\n").unwrap();
+    let image = linker.link(script).unwrap();
+
+    // sections with the same name are placed in the order of the objects
+    assert_eq!(
+        image.to_binary(),
+        vec![0x20, 0x09, 0xe0, 0x20, 0x09, 0xe0, 0x4c, 0x00, 0xe0, 0x60, 0x03, 0x04]
+    );
+    assert_eq!(linker.find_symbol("putc"), Some(0xe009));
+    let listing = linker.listing();
+    assert!(
+        listing.contains("lib.s\ne009  60               2  putc: rts\n"),
+        "{}",
+        listing
+    );
+}
+
+fn serial_library() -> Archive {
+    let mut archive = Archive::new();
+    let members = [
+        ("putc", ".global putc\nputc: sta ACIA\nrts\n"),
+        ("puts", ".global puts\nputs: jsr putc\nrts\n"),
+        ("acia", ".global ACIA\nACIA = $8000\n"),
+        ("getc", ".global getc\ngetc: lda ACIA\nrts\n"),
+    ];
+    for (name, source) in members {
+        let object = compile(&format!("{}.s", name), source);
+        archive.add_member(&format!("{}.o", name), object);
+    }
+    archive
+}
+
+#[test]
+fn archive_round_trip() {
+    let archive = serial_library();
+    let text = archive.write();
+    assert!(
+        text.starts_with(
+            "retro-lang archive 1\nindex putc 0\nindex puts 1\nindex ACIA 2\nindex getc 3\n"
+        ),
+        "{}",
+        text
+    );
+    assert!(text.contains(
+        "\
+member acia.o 4
+retro-lang object 1
+file 0 acia.s
+const ACIA 0 2 32768
+global ACIA 0 2
+member getc.o"
+    ));
+
+    let archive = Archive::read(&text).unwrap();
+    assert_eq!(
+        archive.member_names(),
+        vec!["putc.o", "puts.o", "acia.o", "getc.o"]
+    );
+    assert_eq!(archive.write(), text);
+}
+
+#[test]
+fn invalid_archives() {
+    let read_error = |text: &str| Archive::read(text).err().unwrap();
+
+    assert_eq!(read_error("retro-lang object 1\n"), "not an archive");
+    let header = "retro-lang archive 1\n";
+    assert_eq!(
+        read_error(&format!("{}index putc\n", header)),
+        "line 2: expected 2 fields"
+    );
+    assert_eq!(
+        read_error(&format!("{}member putc.o 2\nretro-lang object 1\n", header)),
+        "line 2: member putc.o is truncated"
+    );
+    assert_eq!(
+        read_error(&format!(
+            "{}member putc.o 2\nretro-lang object 1\nbytes 00\n",
+            header
+        )),
+        "member putc.o: line 2: bytes record outside of a section"
+    );
+    assert_eq!(
+        read_error(&format!("{}index putc 0\n", header)),
+        "index entry of putc refers to a missing member"
+    );
+}
+
+#[test]
+fn link_archive_members() {
+    let main = compile("main.s", "start: jsr puts\njmp start\n");
+    let mut linker = Linker::new();
+    linker.add_object(main);
+    linker.add_archive(Archive::read(&serial_library().write()).unwrap());
+    let script = ldscript::parse(".text @$e000\n").unwrap();
+    let image = linker.link(script).unwrap();
+
+    // puts needs putc, which needs ACIA. members are added in the order they
+    // are needed and getc isn't used by anything.
+    assert_eq!(
+        image.to_binary(),
+        vec![
+            0x20, 0x06, 0xe0, 0x4c, 0x00, 0xe0, // main
+            0x20, 0x0a, 0xe0, 0x60, // puts
+            0x8d, 0x00, 0x80, 0x60, // putc
+        ]
+    );
+    assert_eq!(linker.find_symbol("getc"), None);
+
+    // members are only used for symbols that are still undefined
+    let main = compile("main.s", "jsr putc\nputc: rts\n");
+    let mut linker = Linker::new();
+    linker.add_object(main);
+    linker.add_archive(serial_library());
+    let image = linker
+        .link(ldscript::parse(".text @$e000\n").unwrap())
+        .unwrap();
+    assert_eq!(image.to_binary(), vec![0x20, 0x03, 0xe0, 0x60]);
+
+    // symbols no member defines are still reported
+    let mut linker = Linker::new();
+    linker.add_object(compile("main.s", "jsr printf\n"));
+    linker.add_archive(serial_library());
+    let errors = linker
+        .link(ldscript::parse(".text @$e000\n").unwrap())
+        .unwrap_err();
+    assert_eq!(
+        messages(errors),
+        vec!["undefined reference to symbol printf"]
+    );
+}
+
+#[test]
+fn extern_symbols_must_not_be_defined() {
+    let mut codegen = CodeGenerator::new();
+    let mut parser = AsmParser::new(".import putc\nputc: rts\n");
+    parser.set_file_name("main.s");
+    parser.parse(&mut codegen);
+    assert!(parser.diagnostics("").is_empty());
+
+    assert_eq!(
+        messages(codegen.compile().err().unwrap()),
+        vec!["symbol putc is declared .extern at main.s:1 but defined at main.s:2"]
+    );
+}
+
+#[test]
+fn duplicate_symbols_across_sources() {
+    let mut codegen = CodeGenerator::new();
+    for (name, text) in [("a.s", "start: nop\n"), ("b.s", "loop: nop\nstart: rts\n")] {
+        let mut parser = AsmParser::new(text);
+        parser.set_file_name(name);
+        parser.parse(&mut codegen);
+        assert!(parser.diagnostics("").is_empty());
+    }
+
+    assert_eq!(
+        messages(codegen.compile().err().unwrap()),
+        vec!["duplicate symbol start: defined at a.s:1 and at b.s:2"]
+    );
+}
+
+#[test]
+fn compile_twice() {
+    // every compile takes the statements pushed since the last one
+    let mut codegen = CodeGenerator::new();
+    for (name, source) in [("a.s", "start: nop\n"), ("b.s", "COUNT = 2\nstart: rts\n")] {
+        let mut parser = AsmParser::new(source);
+        parser.set_file_name(name);
+        parser.parse(&mut codegen);
+    }
+    assert_eq!(
+        messages(codegen.compile().err().unwrap()),
+        vec!["duplicate symbol start: defined at a.s:1 and at b.s:2"]
+    );
+
+    let mut parser = AsmParser::new("COUNT = 3\nstart: ldx #COUNT\n");
+    parser.parse(&mut codegen);
+    let image = codegen
+        .link(ldscript::parse(".text @$e000\n").unwrap())
+        .unwrap();
+    assert_eq!(image.to_binary(), vec![0xa2, 0x03]);
+    assert!(codegen.compile().unwrap().write().ends_with("object 1\n"));
+}
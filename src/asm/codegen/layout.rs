@@ -0,0 +1,204 @@
+use super::super::ldscript::{LdScript, MemoryRegion};
+use std::collections::HashMap;
+
+/// Addresses the linker assigned to a section. `addr` is the run address
+/// labels are resolved against, `load_addr` the address the section's
+/// bytes are stored at in the image. NOLOAD sections only occupy
+/// addresses and have no bytes in the image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    name: String,
+    addr: u16,
+    load_addr: u16,
+    size: u32,
+    noload: bool,
+}
+
+impl Placement {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn addr(&self) -> u16 {
+        self.addr
+    }
+
+    pub fn load_addr(&self) -> u16 {
+        self.load_addr
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn is_noload(&self) -> bool {
+        self.noload
+    }
+
+    /// First address after the section
+    pub fn end(&self) -> u32 {
+        self.addr as u32 + self.size
+    }
+
+    /// Address ranges occupied by the section, the load image is included
+    /// if it's stored elsewhere.
+    pub fn ranges(&self) -> Vec<(u32, u32)> {
+        let mut ranges = vec![(self.addr as u32, self.end())];
+        if self.load_addr != self.addr {
+            ranges.push((self.load_addr as u32, self.load_addr as u32 + self.size));
+        }
+        ranges
+    }
+}
+
+struct Layout<'a> {
+    script: &'a LdScript,
+    // next free address of each region
+    region_cursors: HashMap<&'a str, u32>,
+    errors: Vec<String>,
+}
+
+/// Assigns addresses to every section of the linker script. Sections go to
+/// their fixed address if they have one, otherwise to the next free address
+/// of their region or right after the previous section. The load address is
+/// assigned the same way if one is given, otherwise it's the run address.
+pub fn place_sections<F>(script: &LdScript, section_size: F) -> Result<Vec<Placement>, Vec<String>>
+where
+    F: Fn(&str) -> usize,
+{
+    let mut layout = Layout {
+        script,
+        region_cursors: HashMap::new(),
+        errors: vec![],
+    };
+    let mut placements: Vec<Placement> = vec![];
+    let mut current_addr: u32 = 0;
+
+    for section in script.sections().iter() {
+        let size = section_size(section.name()) as u32;
+        let name = section.name();
+        let addr = layout.place(
+            name,
+            "",
+            section.addr(),
+            section.region(),
+            current_addr,
+            size,
+        );
+        let load_addr = if section.load_addr().is_some() || section.load_region().is_some() {
+            let load_addr = section.load_addr();
+            layout.place(
+                name,
+                "load image of ",
+                load_addr,
+                section.load_region(),
+                addr,
+                size,
+            )
+        } else {
+            addr
+        };
+
+        current_addr = addr + size;
+        placements.push(Placement {
+            name: name.into(),
+            addr: addr as u16,
+            load_addr: load_addr as u16,
+            size,
+            noload: section.is_noload(),
+        });
+    }
+
+    let mut errors = layout.errors;
+    errors.append(&mut check_overlaps(&placements));
+    if errors.is_empty() {
+        Ok(placements)
+    } else {
+        Err(errors)
+    }
+}
+
+impl<'a> Layout<'a> {
+    fn place(
+        &mut self,
+        name: &str,
+        what: &str,
+        addr: Option<u16>,
+        region: Option<&str>,
+        fallback_addr: u32,
+        size: u32,
+    ) -> u32 {
+        let region = region.and_then(|region_name| self.find_region(name, region_name));
+        let addr = match (addr, region) {
+            (Some(addr), _) => addr as u32,
+            (None, Some(region)) => self
+                .region_cursors
+                .get(region.name())
+                .copied()
+                .unwrap_or(region.start() as u32),
+            (None, None) => fallback_addr,
+        };
+
+        // the addition can't overflow, addresses are kept in 32 bits here
+        let end = addr + size;
+        if end > 0x10000 {
+            self.errors.push(format!(
+                "{}section .{} (size {} at ${:04x}) extends beyond the address space",
+                what, name, size, addr
+            ));
+        } else if let Some(region) = region {
+            if addr < region.start() as u32 || end > region.end() {
+                self.errors.push(format!(
+                    "{}section .{} ({}) does not fit into region {} ({})",
+                    what,
+                    name,
+                    fmt_range(addr, end),
+                    region.name(),
+                    fmt_range(region.start() as u32, region.end())
+                ));
+            }
+        }
+
+        if let Some(region) = region {
+            self.region_cursors.insert(region.name(), end);
+        }
+        addr
+    }
+
+    fn find_region(&mut self, name: &str, region_name: &str) -> Option<&'a MemoryRegion> {
+        let region = self.script.region(region_name);
+        if region.is_none() {
+            self.errors
+                .push(format!("section .{}: unknown region {}", name, region_name));
+        }
+        region
+    }
+}
+
+fn check_overlaps(placements: &[Placement]) -> Vec<String> {
+    // neither the run nor the load addresses of two sections may overlap
+    let mut errors = vec![];
+    let placements: Vec<&Placement> = placements.iter().filter(|p| p.size > 0).collect();
+    for (i, a) in placements.iter().enumerate() {
+        for b in placements[i + 1..].iter() {
+            for (a_start, a_end) in a.ranges() {
+                for (b_start, b_end) in b.ranges() {
+                    if a_start < b_end && b_start < a_end {
+                        errors.push(format!(
+                            "sections .{} ({}) and .{} ({}) overlap",
+                            a.name,
+                            fmt_range(a_start, a_end),
+                            b.name,
+                            fmt_range(b_start, b_end)
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    errors
+}
+
+fn fmt_range(start: u32, end: u32) -> String {
+    format!("${:04x}-${:04x}", start, end.saturating_sub(1))
+}
@@ -0,0 +1,516 @@
+use super::{
+    archive::Archive,
+    codeblob::CodeBlob,
+    error_at,
+    layout::{place_sections, Placement},
+    listing, mapfile,
+    object::ObjectFile,
+    symfile::{self, SymbolFormat},
+    symtab::SymbolTable,
+};
+use crate::asm::{
+    image::Image,
+    ldscript::{LdScript, MemoryRegion},
+    model::{EvalError, Expr, SourceLoc},
+};
+use crate::errors::{Diagnostic, Diagnostics};
+use std::collections::{HashMap, HashSet};
+
+/// Part of an output section contributed by one object file. The chunks of
+/// a section are placed one after another in the order the objects were
+/// added.
+pub struct Chunk {
+    section: String,
+    blob: CodeBlob,
+    // index of the object the chunk belongs to
+    unit: usize,
+    // run address, known after placing the sections
+    addr: u16,
+}
+
+impl Chunk {
+    pub fn section(&self) -> &str {
+        &self.section
+    }
+
+    pub fn blob(&self) -> &CodeBlob {
+        &self.blob
+    }
+
+    pub fn addr(&self) -> u16 {
+        self.addr
+    }
+}
+
+/// Symbols of one object file. Only the global ones are visible to other
+/// objects, every object can have local symbols of the same name.
+struct Unit {
+    // all labels and constants of the object, filled in while linking
+    symbols: SymbolTable,
+    constants: Vec<(String, Expr, SourceLoc)>,
+    globals: Vec<(String, SourceLoc)>,
+    undefined: Vec<String>,
+}
+
+impl Unit {
+    fn is_global(&self, name: &str) -> bool {
+        self.globals.iter().any(|(global, _)| global == name)
+    }
+
+    fn find(&self, name: &str, globals: &SymbolTable) -> Option<u16> {
+        self.symbols.find(name).or_else(|| globals.find(name))
+    }
+}
+
+/// Combines object files into an image according to a linker script and
+/// keeps the result around for listings, map files and symbol files.
+pub struct Linker {
+    chunks: Vec<Chunk>,
+    units: Vec<Unit>,
+    // global symbols of all objects and the linker defined ones
+    symbols: SymbolTable,
+    archives: Vec<Archive>,
+    placements: Vec<Placement>,
+    regions: Vec<MemoryRegion>,
+}
+
+impl Default for Linker {
+    fn default() -> Self {
+        Linker::new()
+    }
+}
+
+impl Linker {
+    pub fn new() -> Linker {
+        Linker {
+            chunks: vec![],
+            units: vec![],
+            symbols: SymbolTable::new_with_registers(),
+            archives: vec![],
+            placements: vec![],
+            regions: vec![],
+        }
+    }
+
+    pub fn add_object(&mut self, object: ObjectFile) {
+        let unit = self.units.len();
+        let undefined = object
+            .undefined_symbols()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        for (section, blob) in object.sections {
+            self.chunks.push(Chunk {
+                section,
+                blob,
+                unit,
+                addr: 0,
+            });
+        }
+        self.units.push(Unit {
+            symbols: SymbolTable::new(),
+            constants: object.constants,
+            globals: object.globals,
+            undefined,
+        });
+    }
+
+    /// Adds a library whose members are only linked if they define a symbol
+    /// that the program refers to but doesn't define otherwise.
+    pub fn add_archive(&mut self, archive: Archive) {
+        self.archives.push(archive);
+    }
+
+    pub fn link(&mut self, script: LdScript) -> Result<Image, Diagnostics> {
+        self.add_archive_members();
+        self.check_globals().map_err(Diagnostics::from_messages)?;
+        self.check_sections(&script)
+            .map_err(Diagnostics::from_messages)?;
+        let placements = place_sections(&script, |name| {
+            self.section_chunks(name)
+                .map(|chunk| chunk.blob.size())
+                .sum()
+        })
+        .map_err(Diagnostics::from_messages)?;
+        self.assign_chunk_addresses(&placements);
+        self.resolve_all_symbols(&placements)?;
+
+        let mut image = Image::new();
+        for placement in placements.iter().filter(|p| !p.is_noload()) {
+            let data: Vec<u8> = self
+                .section_chunks(placement.name())
+                .flat_map(|chunk| chunk.blob.bytes().iter().copied())
+                .collect();
+            if !data.is_empty() {
+                image.add_segment(placement.load_addr(), data);
+            }
+        }
+        self.placements = placements;
+        self.regions = script.regions().to_vec();
+        Ok(image)
+    }
+
+    pub fn listing(&self) -> String {
+        listing::write(&self.placements, &self.chunks, &self.all_symbols())
+    }
+
+    pub fn map(&self) -> String {
+        mapfile::write(&self.placements, &self.regions, &self.all_symbols())
+    }
+
+    pub fn export_symbols(&self, format: SymbolFormat) -> String {
+        symfile::write(&self.exported_symbols(), format)
+    }
+
+    /// Value of a global symbol after linking
+    pub fn find_symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.find(name)
+    }
+
+    fn all_symbols(&self) -> Vec<(&str, u16)> {
+        // local symbols of different objects may share a name, they are
+        // all listed
+        let mut symbols = self.symbols.sorted();
+        for unit in self.units.iter() {
+            let locals = unit.symbols.sorted().into_iter();
+            symbols.extend(locals.filter(|(name, _)| !unit.is_global(name)));
+        }
+        symbols.sort();
+        symbols
+    }
+
+    fn exported_symbols(&self) -> Vec<(&str, u16)> {
+        // unlike in listings, every name must be unique, so a symbol file
+        // can be included into source. local symbols are left out if
+        // another object or a global symbol uses the same name.
+        let globals = self.symbols.sorted();
+        let mut locals: HashMap<&str, Vec<u16>> = HashMap::new();
+        for unit in self.units.iter() {
+            for (name, value) in unit.symbols.sorted() {
+                if !unit.is_global(name) {
+                    locals.entry(name).or_default().push(value);
+                }
+            }
+        }
+        let unique_locals = locals
+            .into_iter()
+            .filter_map(|(name, values)| match values[..] {
+                [value] if self.symbols.find_defined(name).is_none() => Some((name, value)),
+                _ => None,
+            });
+
+        let mut symbols: Vec<(&str, u16)> = globals.into_iter().chain(unique_locals).collect();
+        symbols.sort();
+        symbols
+    }
+
+    fn add_archive_members(&mut self) {
+        // members may refer to symbols of other members or archives, so the
+        // archives are searched again until no more members get added.
+        // members follow all objects in their sections.
+        let mut archives = std::mem::take(&mut self.archives);
+        loop {
+            let mut added = false;
+            for archive in archives.iter_mut() {
+                let undefined = self.undefined_symbols();
+                for object in archive.take_members(&undefined) {
+                    self.add_object(object);
+                    added = true;
+                }
+            }
+            if !added {
+                break;
+            }
+        }
+        self.archives = archives;
+    }
+
+    fn undefined_symbols(&self) -> HashSet<&str> {
+        let globals: HashSet<&str> = self
+            .units
+            .iter()
+            .flat_map(|unit| unit.globals.iter().map(|(name, _)| name.as_str()))
+            .collect();
+        // pseudo registers are always defined
+        self.units
+            .iter()
+            .flat_map(|unit| unit.undefined.iter().map(String::as_str))
+            .filter(|name| !globals.contains(name) && self.symbols.find(name).is_none())
+            .collect()
+    }
+
+    fn check_globals(&self) -> Result<(), Vec<String>> {
+        let mut definitions: HashMap<&str, &SourceLoc> = HashMap::new();
+        let mut errors = vec![];
+        for (name, loc) in self.units.iter().flat_map(|unit| unit.globals.iter()) {
+            match definitions.get(name.as_str()) {
+                Some(first) => errors.push(format!(
+                    "duplicate global symbol {}: defined at {} and at {}",
+                    name, first, loc
+                )),
+                None => {
+                    definitions.insert(name, loc);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn section_chunks<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Chunk> {
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.section == name)
+    }
+
+    fn check_sections(&self, script: &LdScript) -> Result<(), Vec<String>> {
+        let mut errors: Vec<String> = self
+            .chunks
+            .iter()
+            .filter(|chunk| {
+                chunk.blob.size() > 0
+                    && !script.sections().iter().any(|s| s.name() == chunk.section)
+            })
+            .map(|chunk| {
+                format!(
+                    "section .{} is not placed by the linker script",
+                    chunk.section
+                )
+            })
+            .collect();
+
+        // NOLOAD sections are not part of the image, so anything but
+        // reserved space would be lost
+        for section in script.sections().iter().filter(|s| s.is_noload()) {
+            if self
+                .section_chunks(section.name())
+                .any(|chunk| chunk.blob.has_contents())
+            {
+                errors.push(format!(
+                    "section .{} is NOLOAD but contains code or data",
+                    section.name()
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            errors.sort();
+            errors.dedup();
+            Err(errors)
+        }
+    }
+
+    fn assign_chunk_addresses(&mut self, placements: &[Placement]) {
+        for placement in placements.iter() {
+            let mut addr = placement.addr();
+            for chunk in self
+                .chunks
+                .iter_mut()
+                .filter(|chunk| chunk.section == placement.name())
+            {
+                chunk.addr = addr;
+                addr = addr.wrapping_add(chunk.blob.size() as u16);
+            }
+        }
+    }
+
+    fn resolve_constants(&mut self) -> Vec<Diagnostic> {
+        // constants may depend on the global constants of other objects, so
+        // all objects take turns until no more progress is made.
+        let mut errors = vec![];
+        loop {
+            let mut resolved = false;
+            for unit in self.units.iter_mut() {
+                let globals = &self.symbols;
+                let lookup = |name: &str| unit.symbols.find(name).or_else(|| globals.find(name));
+                let values = eval_known_constants(&mut unit.constants, lookup, &mut errors);
+                for (name, value) in values {
+                    if unit.is_global(&name) {
+                        self.symbols.insert(&name, value);
+                    }
+                    unit.symbols.insert(&name, value);
+                    resolved = true;
+                }
+            }
+            if !resolved {
+                break;
+            }
+        }
+
+        // the constants left over refer to undefined symbols or to each
+        // other. every cycle is reported once, constants that merely depend
+        // on one aren't reported at all.
+        let left: Vec<Vec<(String, Expr, SourceLoc)>> = self
+            .units
+            .iter_mut()
+            .map(|unit| std::mem::take(&mut unit.constants))
+            .collect();
+        let mut reported: HashSet<(usize, &str)> = HashSet::new();
+        for (i, constants) in left.iter().enumerate() {
+            for (name, expr, loc) in constants.iter() {
+                if reported.contains(&(i, name.as_str())) {
+                    continue;
+                }
+                match self.constant_cycle(&left, i, name) {
+                    Some(cycle) if cycle.contains(&(i, name.as_str())) => {
+                        let message = format!("circular definition of {}", name);
+                        errors.push(error_at(loc, &message));
+                        reported.extend(cycle);
+                    }
+                    Some(_) => {}
+                    None => {
+                        let unit = &self.units[i];
+                        if let Err(error) = expr.eval(&|name| unit.find(name, &self.symbols)) {
+                            let message = format!("constant {}: {}", name, error);
+                            errors.push(error_at(loc, &message));
+                        }
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    /// Follows the left over constant `name` of a unit to the left over
+    /// constant it refers to, and so on. Returns the cycle this ends in, or
+    /// None if it ends at a symbol that isn't defined at all.
+    fn constant_cycle<'a>(
+        &self,
+        left: &'a [Vec<(String, Expr, SourceLoc)>],
+        unit: usize,
+        name: &'a str,
+    ) -> Option<Vec<(usize, &'a str)>> {
+        let find = |unit: usize, name: &str| {
+            left[unit]
+                .iter()
+                .find(|(constant, _, _)| constant == name)
+                .map(|(constant, expr, _)| (unit, constant.as_str(), expr))
+        };
+
+        let mut path = vec![(unit, name)];
+        let mut expr = find(unit, name)?.2;
+        loop {
+            let (i, _) = *path.last().unwrap();
+            let symbol = match expr.eval(&|name| self.units[i].find(name, &self.symbols)) {
+                Err(EvalError::UndefinedSymbol(symbol)) => symbol,
+                _ => return None,
+            };
+            // local constants come first, like when looking up symbols
+            let (next, name, next_expr) = find(i, &symbol).or_else(|| {
+                (0..left.len())
+                    .filter(|&j| self.units[j].is_global(&symbol))
+                    .find_map(|j| find(j, &symbol))
+            })?;
+            if let Some(start) = path.iter().position(|&p| p == (next, name)) {
+                return Some(path.split_off(start));
+            }
+            path.push((next, name));
+            expr = next_expr;
+        }
+    }
+
+    fn define_section_symbols(&mut self, placements: &[Placement]) {
+        // section boundaries, e.g. for clearing memory or setting up a heap,
+        // and the addresses startup code needs to copy a section from its
+        // load address to its run address. all of them are available to
+        // constants and relocations. __x_end is the first address after the
+        // section, which wraps around to 0 for a section ending at $ffff.
+        for placement in placements.iter() {
+            let name = placement.name();
+            let symbols = [
+                ("start", placement.addr()),
+                ("end", placement.end() as u16),
+                ("size", placement.size() as u16),
+                ("load_start", placement.load_addr()),
+                ("run_start", placement.addr()),
+            ];
+            for (suffix, value) in symbols {
+                let symbol = format!("__{}_{}", name, suffix);
+                self.symbols.insert(&symbol, value);
+            }
+        }
+    }
+
+    fn resolve_all_symbols(&mut self, placements: &[Placement]) -> Result<(), Vec<Diagnostic>> {
+        self.define_section_symbols(placements);
+        let is_placed = |chunk: &Chunk| placements.iter().any(|p| p.name() == chunk.section);
+        for chunk in self.chunks.iter().filter(|chunk| is_placed(chunk)) {
+            let unit = &mut self.units[chunk.unit];
+            unit.symbols.insert_table(chunk.blob.symbols(), chunk.addr);
+        }
+        for unit in self.units.iter() {
+            for (name, _) in unit.globals.iter() {
+                if let Some(value) = unit.symbols.find(name) {
+                    self.symbols.insert(name, value);
+                }
+            }
+        }
+
+        // now that all labels are known, the constants must resolve
+        let mut errors = self.resolve_constants();
+
+        for chunk in self.chunks.iter_mut().filter(|chunk| is_placed(chunk)) {
+            let unit = &self.units[chunk.unit];
+            let lookup = |name: &str| unit.find(name, &self.symbols);
+            errors.append(&mut chunk.blob.resolve_symbols(chunk.addr, lookup));
+        }
+
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Evaluates constants that may refer to each other in any order until no
+/// more progress is made. The ones left over depend on symbols that aren't
+/// known yet.
+pub fn eval_constants(
+    constants: &mut Vec<(String, Expr, SourceLoc)>,
+    symbols: &mut SymbolTable,
+) -> Vec<Diagnostic> {
+    let mut errors = vec![];
+    loop {
+        let values = eval_known_constants(constants, |name| symbols.find(name), &mut errors);
+        if values.is_empty() {
+            break errors;
+        }
+        for (name, value) in values {
+            symbols.insert(&name, value);
+        }
+    }
+}
+
+/// Takes the constants that only depend on known symbols out of the list and
+/// returns their values.
+fn eval_known_constants<F>(
+    constants: &mut Vec<(String, Expr, SourceLoc)>,
+    lookup: F,
+    errors: &mut Vec<Diagnostic>,
+) -> Vec<(String, u16)>
+where
+    F: Fn(&str) -> Option<u16>,
+{
+    let mut values = vec![];
+    constants.retain(|(name, expr, loc)| {
+        let message = match expr.eval(&lookup) {
+            Ok(value) if (0..=0xffff).contains(&value) => {
+                values.push((name.clone(), value as u16));
+                return false;
+            }
+            Ok(value) => format!("constant {} ({}) does not fit into 16 bits", name, value),
+            Err(EvalError::UndefinedSymbol(_)) => return true,
+            Err(error) => format!("constant {}: {}", name, error),
+        };
+        errors.push(error_at(loc, &message));
+        false
+    });
+    values
+}
@@ -0,0 +1,89 @@
+use super::{codeblob::CodeBlob, layout::Placement, linker::Chunk};
+use std::fmt::Write;
+
+// bytes shown per row, longer data continues on the following rows
+const BYTES_PER_ROW: usize = 4;
+
+/// Lists every placed section in linker script order. Each source line that
+/// emitted statements gets a row with its run address and bytes; a line
+/// naming the file precedes the rows whenever the file changes.
+pub fn write(placements: &[Placement], chunks: &[Chunk], symbols: &[(&str, u16)]) -> String {
+    let mut listing = String::new();
+    for placement in placements.iter() {
+        let chunks: Vec<&Chunk> = chunks
+            .iter()
+            .filter(|chunk| chunk.section() == placement.name() && !chunk.blob().lines().is_empty())
+            .collect();
+        if chunks.is_empty() {
+            continue;
+        }
+        writeln!(
+            listing,
+            "section .{} ${:04x}-${:04x} ({} bytes)",
+            placement.name(),
+            placement.addr(),
+            placement.end().saturating_sub(1),
+            placement.size()
+        )
+        .unwrap();
+        for chunk in chunks {
+            write_lines(&mut listing, chunk.addr(), chunk.blob());
+        }
+        listing.push('\n');
+    }
+
+    let width = symbols
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    listing.push_str("symbols\n");
+    for (name, value) in symbols {
+        writeln!(listing, "{:width$}  ${:04x}", name, value, width = width).unwrap();
+    }
+    listing
+}
+
+fn write_lines(listing: &mut String, base_addr: u16, blob: &CodeBlob) {
+    let lines = blob.lines();
+    let mut current_file = None;
+    for (i, line) in lines.iter().enumerate() {
+        if line.loc.file.is_some() && line.loc.file != current_file {
+            current_file = line.loc.file.clone();
+            writeln!(listing, "{}", current_file.as_ref().unwrap()).unwrap();
+        }
+
+        let start = line.offset as usize;
+        let end = lines
+            .get(i + 1)
+            .map_or(blob.size(), |next| next.offset as usize);
+        let bytes = if line.reserved {
+            &[][..]
+        } else {
+            &blob.bytes()[start..end]
+        };
+
+        let mut rows = bytes.chunks(BYTES_PER_ROW);
+        let addr = base_addr.wrapping_add(line.offset);
+        writeln!(
+            listing,
+            "{:04x}  {:<width$}{:>6}  {}",
+            addr,
+            hex_bytes(rows.next().unwrap_or(&[])),
+            line.loc.line,
+            line.loc.text,
+            width = BYTES_PER_ROW * 3
+        )
+        .unwrap();
+
+        let mut addr = addr;
+        for row in rows {
+            addr = addr.wrapping_add(BYTES_PER_ROW as u16);
+            writeln!(listing, "{:04x}  {}", addr, hex_bytes(row).trim_end()).unwrap();
+        }
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x} ", byte)).collect()
+}
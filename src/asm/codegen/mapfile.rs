@@ -0,0 +1,114 @@
+use super::layout::Placement;
+use crate::asm::ldscript::MemoryRegion;
+use std::fmt::Write;
+
+pub fn write(
+    placements: &[Placement],
+    regions: &[MemoryRegion],
+    symbols: &[(&str, u16)],
+) -> String {
+    let mut map = String::new();
+
+    let width = placements
+        .iter()
+        .map(|placement| placement.name().len() + 1)
+        .max()
+        .unwrap_or(0)
+        .max("section".len());
+    writeln!(
+        map,
+        "{:width$}  start  end    size   load",
+        "section",
+        width = width
+    )
+    .unwrap();
+    for placement in placements.iter() {
+        let load = if placement.is_noload() {
+            "NOLOAD".to_string()
+        } else if placement.load_addr() != placement.addr() {
+            format!("${:04x}", placement.load_addr())
+        } else {
+            String::new()
+        };
+        let line = format!(
+            "{:width$}  ${:04x}  {}  {:5}  {}",
+            format!(".{}", placement.name()),
+            placement.addr(),
+            fmt_end(placement.addr() as u32, placement.end()),
+            placement.size(),
+            load,
+            width = width
+        );
+        writeln!(map, "{}", line.trim_end()).unwrap();
+    }
+
+    if !regions.is_empty() {
+        let width = regions
+            .iter()
+            .map(|region| region.name().len())
+            .max()
+            .unwrap()
+            .max("region".len());
+        writeln!(
+            map,
+            "\n{:width$}  start  end    size   used   free",
+            "region",
+            width = width
+        )
+        .unwrap();
+        for region in regions.iter() {
+            let used = used_bytes(region, placements);
+            writeln!(
+                map,
+                "{:width$}  ${:04x}  {}  {:5}  {:5}  {:5}",
+                region.name(),
+                region.start(),
+                fmt_end(region.start() as u32, region.end()),
+                region.size(),
+                used,
+                region.size() - used,
+                width = width
+            )
+            .unwrap();
+        }
+    }
+
+    let mut symbols = symbols.to_vec();
+    let width = symbols
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    map.push_str("\nsymbols by name\n");
+    for (name, value) in symbols.iter() {
+        writeln!(map, "{:width$}  ${:04x}", name, value, width = width).unwrap();
+    }
+    symbols.sort_by_key(|(name, value)| (*value, *name));
+    map.push_str("\nsymbols by address\n");
+    for (name, value) in symbols.iter() {
+        writeln!(map, "${:04x}  {}", value, name).unwrap();
+    }
+    map
+}
+
+fn used_bytes(region: &MemoryRegion, placements: &[Placement]) -> u32 {
+    // placements never overlap, so the overlapping parts can be summed up
+    placements
+        .iter()
+        .flat_map(|placement| placement.ranges())
+        .map(|(start, end)| {
+            let start = start.max(region.start() as u32);
+            let end = end.min(region.end());
+            end.saturating_sub(start)
+        })
+        .sum()
+}
+
+fn fmt_end(start: u32, end: u32) -> String {
+    // last address of a range, empty ranges don't have one
+    if end > start {
+        format!("${:04x}", end - 1)
+    } else {
+        "-    ".into()
+    }
+}
@@ -1,23 +1,54 @@
+mod archive;
 mod codeblob;
+mod layout;
+mod linker;
+mod listing;
+mod mapfile;
+mod object;
+mod symfile;
 mod symtab;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use self::codeblob::CodeBlob;
-use super::{ldscript::LdSection, model::AsmStmt, parser::SectionSink};
+use super::{
+    image::Image,
+    ldscript::LdScript,
+    model::{AsmStmt, Expr, SourceLoc, SourceStmt},
+    parser::SectionSink,
+};
+use crate::errors::{Diagnostic, Diagnostics};
+pub use archive::Archive;
+use linker::eval_constants;
+pub use linker::Linker;
+pub use object::ObjectFile;
+pub use symfile::SymbolFormat;
+pub use symtab::is_register;
 use symtab::SymbolTable;
 
 #[rustfmt::skip]
 mod opcode_table;
 pub use opcode_table::get_opcode;
 
+#[cfg(test)]
+mod codegen_tests;
+
 pub struct CodeGenerator {
-    sections: HashMap<String, Vec<AsmStmt>>,
-    blobs: HashMap<String, CodeBlob>,
+    sections: HashMap<String, Vec<SourceStmt>>,
+    // labels and constants in the order they were pushed
+    definitions: Vec<(String, SourceLoc)>,
     symbols: SymbolTable,
+    constants: Vec<(String, Expr, SourceLoc)>,
+    linker: Linker,
 }
 
 impl SectionSink for CodeGenerator {
-    fn push_section(&mut self, name: &str, stmts: Vec<AsmStmt>) {
+    fn push_section(&mut self, name: &str, stmts: Vec<SourceStmt>) {
+        for stmt in stmts.iter() {
+            if let AsmStmt::Label(label) | AsmStmt::ConstLabel(label, _) = &stmt.stmt {
+                self.definitions.push((label.clone(), stmt.loc.clone()));
+            }
+        }
+
         let mut stmts = stmts;
         if let Some(section_stmts) = self.sections.get_mut(name) {
             section_stmts.append(&mut stmts);
@@ -27,111 +58,214 @@ impl SectionSink for CodeGenerator {
     }
 }
 
+impl Default for CodeGenerator {
+    fn default() -> Self {
+        CodeGenerator::new()
+    }
+}
+
 impl CodeGenerator {
     pub fn new() -> CodeGenerator {
         CodeGenerator {
             sections: HashMap::new(),
-            blobs: HashMap::new(),
+            definitions: vec![],
             symbols: SymbolTable::new_with_registers(),
+            constants: vec![],
+            linker: Linker::new(),
         }
     }
 
-    pub fn link(&mut self, sections_to_link: Vec<LdSection>) -> Result<Vec<u8>, Vec<String>> {
-        self.collect_symbols();
-        self.generate_statements();
-        self.resolve_all_symbols(&sections_to_link)?;
-
-        let mut binary: Vec<u8> = vec![];
-        let mut current_addr = sections_to_link[0].load_addr().unwrap();
-        for section in sections_to_link.iter() {
-            let load_addr = match section.load_addr() {
-                Some(addr) => {
-                    let padding = addr - current_addr;
-                    let mut padding = vec![0u8; padding as usize];
-                    binary.append(&mut padding);
-                    addr
-                }
-                None => current_addr,
-            };
+    /// Generates code for all statements pushed so far. Symbols that aren't
+    /// defined yet are left for the linker to resolve, so the result can be
+    /// linked together with other object files. Only symbols exported with
+    /// `.global` are visible to those. Afterwards the generator is empty
+    /// again, whether compiling succeeded or not, and statements pushed
+    /// from then on go into the next object.
+    pub fn compile(&mut self) -> Result<ObjectFile, Diagnostics> {
+        let object = self.compile_statements();
+        self.sections.clear();
+        self.definitions.clear();
+        self.constants.clear();
+        self.symbols = SymbolTable::new_with_registers();
+        object
+    }
 
-            if let Some(blob) = self.blobs.get_mut(section.name()) {
-                let blob_size = blob.size();
-                blob.dump(&mut binary);
-                current_addr = load_addr + blob_size as u16;
-            }
-        }
-        Ok(binary)
+    fn compile_statements(&mut self) -> Result<ObjectFile, Diagnostics> {
+        self.check_definitions()
+            .map_err(Diagnostics::from_messages)?;
+        let definitions = self.collect_symbols()?;
+        let globals = self.collect_globals().map_err(Diagnostics::from_messages)?;
+        let sections = self.generate_statements()?;
+        Ok(ObjectFile::new(sections, definitions, globals))
     }
 
-    fn collect_symbols(&mut self) {
+    /// Compiles the statements and links them on their own.
+    pub fn link(&mut self, script: LdScript) -> Result<Image, Diagnostics> {
+        let object = self.compile()?;
+        self.linker = Linker::new();
+        self.linker.add_object(object);
+        self.linker.link(script)
+    }
+
+    /// Listing of the linked program: address, bytes, line number and source
+    /// text of every statement, followed by the symbol table. Empty before
+    /// linking.
+    pub fn listing(&self) -> String {
+        self.linker.listing()
+    }
+
+    /// Map of the linked program: where each section was placed, how much
+    /// of each memory region is still free and the values of all symbols.
+    /// Empty before linking.
+    pub fn map(&self) -> String {
+        self.linker.map()
+    }
+
+    /// Symbols with their final values in the given format, for loading
+    /// them into an emulator or monitor. Local symbols are left out if their
+    /// name isn't unique, see [`SymbolFormat`]. Empty before linking.
+    pub fn export_symbols(&self, format: SymbolFormat) -> String {
+        self.linker.export_symbols(format)
+    }
+
+    /// Value of a symbol after linking
+    pub fn find_symbol(&self, name: &str) -> Option<u16> {
+        self.linker.find_symbol(name)
+    }
+
+    fn collect_symbols(&mut self) -> Result<Vec<(String, Expr, SourceLoc)>, Vec<Diagnostic>> {
         // fill the symbol table with all constant label assignments
         // from any section so that the zeropage addr mode can be
         // used if it's available for an instruction and the address
         // fits into 8 bits.
         for (_, section_stmts) in self.sections.iter() {
             for stmt in section_stmts.iter() {
-                if let AsmStmt::ConstLabel(name, addr) = stmt {
-                    self.symbols.insert(name, *addr);
+                if let AsmStmt::ConstLabel(name, addr) = &stmt.stmt {
+                    self.constants
+                        .push((name.clone(), addr.clone(), stmt.loc.clone()));
                 }
             }
         }
-    }
 
-    fn generate_statements(&mut self) {
-        for (section_name, stmts) in self.sections.iter() {
-            let mut blob = CodeBlob::new();
+        // the linker evaluates all of them again, including the ones that
+        // depend on labels
+        let definitions = self.constants.clone();
+        let errors = eval_constants(&mut self.constants, &mut self.symbols);
+        if errors.is_empty() {
+            Ok(definitions)
+        } else {
+            Err(errors)
+        }
+    }
 
-            // iterate over all sections and statements and actually generate
-            // code from the model. undefined symbols are reported for relocation.
-            for stmt in stmts.iter() {
-                blob.gen_stmt(stmt, |name| self.symbols.find(name));
+    fn check_definitions(&self) -> Result<(), Vec<String>> {
+        // the parser reports symbols defined twice within a file, this
+        // catches the ones defined in different sources of the same object
+        let mut first_definitions: HashMap<&str, &SourceLoc> = HashMap::new();
+        let mut errors = vec![];
+        for (name, loc) in self.definitions.iter() {
+            match first_definitions.get(name.as_str()) {
+                Some(first) => errors.push(format!(
+                    "duplicate symbol {}: defined at {} and at {}",
+                    name, first, loc
+                )),
+                None => {
+                    first_definitions.insert(name, loc);
+                }
             }
+        }
 
-            self.blobs.insert(section_name.into(), blob);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
     }
 
-    fn resolve_all_symbols(&mut self, link_sections: &Vec<LdSection>) -> Result<(), Vec<String>> {
-        self.iterate_section_blobs(link_sections, |symbols, section_base, blob| {
-            symbols.insert_table(blob.symbols(), section_base);
-            vec![]
-        });
+    fn collect_globals(&self) -> Result<Vec<(String, SourceLoc)>, Vec<String>> {
+        // exporting a symbol that isn't defined here is the same as
+        // importing it, so a shared include file can declare all globals.
+        let definitions: HashMap<&str, &SourceLoc> = self
+            .definitions
+            .iter()
+            .map(|(name, loc)| (name.as_str(), loc))
+            .collect();
+        let mut exported = vec![];
+        let mut imported = vec![];
+        for stmt in self.sections.values().flatten() {
+            match &stmt.stmt {
+                AsmStmt::Global(name) => exported.push(name.as_str()),
+                AsmStmt::Extern(name) => imported.push((name.as_str(), &stmt.loc)),
+                _ => {}
+            }
+        }
 
-        let errors = self.relocate_blobs(link_sections);
-        if errors.len() > 0 {
-            Err(errors)
-        } else {
-            Ok(())
+        let mut errors: Vec<String> = imported
+            .iter()
+            .filter_map(|(name, loc)| {
+                let definition = definitions.get(name)?;
+                Some(format!(
+                    "symbol {} is declared .extern at {} but defined at {}",
+                    name, loc, definition
+                ))
+            })
+            .collect();
+        if !errors.is_empty() {
+            errors.sort();
+            return Err(errors);
         }
-    }
 
-    fn relocate_blobs(&mut self, link_sections: &Vec<LdSection>) -> Vec<String> {
-        self.iterate_section_blobs(link_sections, |symbols, section_base, blob| {
-            blob.resolve_symbols(section_base, symbols)
-        })
+        let mut globals: Vec<(String, SourceLoc)> = exported
+            .into_iter()
+            .filter_map(|name| Some((name.to_string(), (*definitions.get(name)?).clone())))
+            .collect();
+        globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        globals.dedup_by(|(a, _), (b, _)| a == b);
+        Ok(globals)
     }
 
-    fn iterate_section_blobs<F>(&mut self, link_sections: &Vec<LdSection>, f: F) -> Vec<String>
-    where
-        F: Fn(&mut SymbolTable, u16, &mut CodeBlob) -> Vec<String>,
-    {
+    fn generate_statements(&mut self) -> Result<Vec<(String, CodeBlob)>, Vec<Diagnostic>> {
         let mut errors = vec![];
-        let mut current_addr = link_sections[0].load_addr().unwrap();
-        for section in link_sections.iter() {
-            let load_addr = match section.load_addr() {
-                Some(addr) => addr,
-                None => current_addr,
-            };
-
-            // resolve symbols: go over the binary blobs again and fill in the
-            // placeholders with the actual addresses that have accumulated
-            // in the symbol table by now.
-            if let Some(blob) = self.blobs.get_mut(section.name()) {
-                errors.append(&mut f(&mut self.symbols, load_addr, blob));
-                current_addr = load_addr + blob.size() as u16;
+        let mut blobs = vec![];
+        // labels named like a pseudo register shadow it, but their address
+        // is only known after linking
+        let shadowed: HashSet<&str> = self
+            .definitions
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| is_register(name))
+            .collect();
+        let lookup = |name: &str| {
+            if shadowed.contains(name) {
+                self.symbols.find_defined(name)
+            } else {
+                self.symbols.find(name)
+            }
+        };
+        for (section_name, stmts) in self.sections.drain() {
+            let mut blob = CodeBlob::new();
+
+            // iterate over all sections and statements and actually generate
+            // code from the model. undefined symbols are reported for relocation.
+            for stmt in stmts.iter() {
+                blob.gen_stmt(stmt, lookup);
             }
+
+            errors.extend(blob.errors().iter().cloned());
+            blobs.push((section_name, blob));
         }
 
-        errors
+        if errors.is_empty() {
+            blobs.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Ok(blobs)
+        } else {
+            Err(errors)
+        }
     }
 }
+
+/// Error about the statement at `loc`, reported by the code generator or
+/// the linker.
+fn error_at(loc: &SourceLoc, message: &str) -> Diagnostic {
+    Diagnostic::at(loc.file.as_deref(), loc.line, message)
+}
@@ -0,0 +1,403 @@
+use super::{
+    codeblob::{CodeBlob, LineInfo, RelocKind, Relocation},
+    symtab::SymbolTable,
+};
+use crate::asm::{
+    model::{Expr, SourceLoc},
+    AsmParser,
+};
+use std::fmt::Write;
+
+const HEADER: &str = "retro-lang object 1";
+
+// bytes per data record
+const BYTES_PER_RECORD: usize = 32;
+
+/// Code generated from one or more sources that still has to be linked: the
+/// bytes of every section with their labels, relocations and line info, the
+/// constants defined in the sources and which of the symbols are global.
+///
+/// Object files are stored as text with one record per line, e.g.
+///
+/// ```text
+/// retro-lang object 1
+/// file 0 main.s
+/// const ACIA 0 1 32768
+/// global main 0 2
+/// section text data
+/// bytes 20000060
+/// label main 0
+/// reloc 1 abs16 0 3 putc
+/// line 0 - 0 3     jsr putc
+/// ```
+///
+/// Expressions are written in assembler syntax. Constant, global and
+/// relocation records hold the file index and line number of the statement
+/// they come from, `-` stands for a source without a name. Sections that
+/// only reserve space store their size instead of bytes, e.g.
+/// `section bss reserve 256`, and empty sections are left out. Line records
+/// hold the blob offset, `r` for lines that reserve space, the file index,
+/// the line number and the source text.
+#[derive(Default)]
+pub struct ObjectFile {
+    pub(super) sections: Vec<(String, CodeBlob)>,
+    pub(super) constants: Vec<(String, Expr, SourceLoc)>,
+    pub(super) globals: Vec<(String, SourceLoc)>,
+}
+
+impl ObjectFile {
+    pub fn new(
+        sections: Vec<(String, CodeBlob)>,
+        constants: Vec<(String, Expr, SourceLoc)>,
+        globals: Vec<(String, SourceLoc)>,
+    ) -> ObjectFile {
+        ObjectFile {
+            sections,
+            constants,
+            globals,
+        }
+    }
+
+    pub fn write(&self) -> String {
+        let blobs = self.sections.iter().map(|(_, blob)| blob);
+        let locations = self
+            .constants
+            .iter()
+            .map(|(_, _, loc)| loc)
+            .chain(self.globals.iter().map(|(_, loc)| loc))
+            .chain(
+                blobs
+                    .clone()
+                    .flat_map(|blob| blob.lines())
+                    .map(|line| &line.loc),
+            )
+            .chain(
+                blobs
+                    .flat_map(|blob| blob.relocations())
+                    .map(|reloc| &reloc.loc),
+            );
+        let mut files: Vec<&str> = vec![];
+        for loc in locations {
+            if let Some(file) = &loc.file {
+                if !files.contains(&file.as_str()) {
+                    files.push(file);
+                }
+            }
+        }
+        let file_index = |loc: &SourceLoc| match &loc.file {
+            Some(file) => files.iter().position(|f| f == file).unwrap().to_string(),
+            None => "-".into(),
+        };
+
+        let mut text = format!("{}\n", HEADER);
+        for (i, file) in files.iter().enumerate() {
+            writeln!(text, "file {} {}", i, file).unwrap();
+        }
+        for (name, expr, loc) in self.constants.iter() {
+            writeln!(
+                text,
+                "const {} {} {} {}",
+                name,
+                file_index(loc),
+                loc.line,
+                expr
+            )
+            .unwrap();
+        }
+        for (name, loc) in self.globals.iter() {
+            writeln!(text, "global {} {} {}", name, file_index(loc), loc.line).unwrap();
+        }
+
+        for (name, blob) in self.sections.iter() {
+            let is_empty = blob.size() == 0
+                && blob.symbols().sorted().is_empty()
+                && blob.relocations().is_empty();
+            if is_empty {
+                continue;
+            }
+            if blob.has_contents() {
+                writeln!(text, "section {} data", name).unwrap();
+                for bytes in blob.bytes().chunks(BYTES_PER_RECORD) {
+                    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                    writeln!(text, "bytes {}", hex).unwrap();
+                }
+            } else {
+                writeln!(text, "section {} reserve {}", name, blob.size()).unwrap();
+            }
+            for (label, offset) in blob.symbols().sorted() {
+                writeln!(text, "label {} {}", label, offset).unwrap();
+            }
+            for reloc in blob.relocations() {
+                let kind = match reloc.kind {
+                    RelocKind::Abs16 => "abs16",
+                    RelocKind::Abs8 => "abs8",
+                    RelocKind::LowByte => "lo8",
+                    RelocKind::HighByte => "hi8",
+                    RelocKind::Rel8 => "rel8",
+                };
+                writeln!(
+                    text,
+                    "reloc {} {} {} {} {}",
+                    reloc.offset,
+                    kind,
+                    file_index(&reloc.loc),
+                    reloc.loc.line,
+                    reloc.target
+                )
+                .unwrap();
+            }
+            for line in blob.lines() {
+                let reserved = if line.reserved { "r" } else { "-" };
+                writeln!(
+                    text,
+                    "line {} {} {} {} {}",
+                    line.offset,
+                    reserved,
+                    file_index(&line.loc),
+                    line.loc.line,
+                    line.loc.text
+                )
+                .unwrap();
+            }
+        }
+        text
+    }
+
+    /// Symbols the object exports to other objects
+    pub fn global_symbols(&self) -> Vec<&str> {
+        self.globals.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Symbols used by relocations and constants that the object doesn't
+    /// define itself, sorted by name.
+    pub fn undefined_symbols(&self) -> Vec<&str> {
+        let labels = self
+            .sections
+            .iter()
+            .flat_map(|(_, blob)| blob.symbols().sorted().into_iter().map(|(name, _)| name));
+        let defined: Vec<&str> = labels
+            .chain(self.constants.iter().map(|(name, _, _)| name.as_str()))
+            .collect();
+
+        let relocations = self
+            .sections
+            .iter()
+            .flat_map(|(_, blob)| blob.relocations().iter().flat_map(|r| r.target.symbols()));
+        let constants = self
+            .constants
+            .iter()
+            .flat_map(|(_, expr, _)| expr.symbols());
+        let mut undefined: Vec<&str> = relocations
+            .chain(constants)
+            .filter(|name| !defined.contains(name))
+            .collect();
+        undefined.sort();
+        undefined.dedup();
+        undefined
+    }
+
+    /// Reads an object file written by `write`. Errors name the offending
+    /// line of the object file.
+    pub fn read(text: &str) -> Result<ObjectFile, String> {
+        let mut reader = ObjectReader::default();
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err("not an object file".into());
+        }
+        for (i, line) in lines.enumerate() {
+            reader
+                .read_record(line)
+                .map_err(|error| format!("line {}: {}", i + 2, error))?;
+        }
+        reader.finish_section();
+        Ok(reader.object)
+    }
+}
+
+#[derive(Default)]
+struct ObjectReader {
+    object: ObjectFile,
+    files: Vec<String>,
+    section: Option<SectionParts>,
+}
+
+struct SectionParts {
+    name: String,
+    has_contents: bool,
+    bytes: Vec<u8>,
+    symbols: SymbolTable,
+    relocations: Vec<Relocation>,
+    lines: Vec<LineInfo>,
+}
+
+impl ObjectReader {
+    fn read_record(&mut self, record: &str) -> Result<(), String> {
+        let (kind, fields) = record.split_once(' ').unwrap_or((record, ""));
+        match kind {
+            "file" => {
+                let [index, name] = split_fields(fields)?;
+                if parse_number(index)? != self.files.len() {
+                    return Err(format!("file {} out of order", index));
+                }
+                self.files.push(name.into());
+            }
+            "const" => {
+                let [name, file, line, expr] = split_fields(fields)?;
+                let loc = parse_loc(&self.files, file, line)?;
+                let expr = parse_expr(expr)?;
+                self.object.constants.push((name.into(), expr, loc));
+            }
+            "section" => {
+                let [name, contents] = split_fields(fields)?;
+                let (has_contents, bytes) = match contents.split_once(' ') {
+                    None if contents == "data" => (true, vec![]),
+                    Some(("reserve", size)) => {
+                        let size = parse_number(size)?;
+                        if size > 0x10000 {
+                            return Err(format!("section .{} exceeds 64K", name));
+                        }
+                        (false, vec![0; size])
+                    }
+                    _ => return Err(format!("invalid section contents '{}'", contents)),
+                };
+                self.finish_section();
+                self.section = Some(SectionParts {
+                    name: name.into(),
+                    has_contents,
+                    bytes,
+                    symbols: SymbolTable::new(),
+                    relocations: vec![],
+                    lines: vec![],
+                });
+            }
+            "global" => {
+                let [name, file, line] = split_fields(fields)?;
+                let loc = parse_loc(&self.files, file, line)?;
+                self.object.globals.push((name.into(), loc));
+            }
+            "bytes" | "label" | "reloc" | "line" => self.read_section_record(kind, fields)?,
+            _ => return Err(format!("unknown record '{}'", kind)),
+        }
+        Ok(())
+    }
+
+    fn read_section_record(&mut self, kind: &str, fields: &str) -> Result<(), String> {
+        let files = &self.files;
+        let section = self
+            .section
+            .as_mut()
+            .ok_or_else(|| format!("{} record outside of a section", kind))?;
+        let size = section.bytes.len();
+        match kind {
+            "bytes" => {
+                if !section.has_contents {
+                    return Err(format!("bytes record in reserve section .{}", section.name));
+                }
+                if !fields.len().is_multiple_of(2) {
+                    return Err("odd number of hex digits".into());
+                }
+                for i in (0..fields.len()).step_by(2) {
+                    let byte = u8::from_str_radix(&fields[i..i + 2], 16)
+                        .map_err(|_| format!("invalid bytes '{}'", fields))?;
+                    section.bytes.push(byte);
+                }
+                if section.bytes.len() > 0x10000 {
+                    return Err(format!("section .{} exceeds 64K", section.name));
+                }
+            }
+            "label" => {
+                let [name, offset] = split_fields(fields)?;
+                let offset = parse_offset(offset, 0, size)?;
+                section.symbols.insert(name, offset);
+            }
+            "reloc" => {
+                let [offset, kind, file, line, target] = split_fields(fields)?;
+                let (kind, width) = match kind {
+                    "abs16" => (RelocKind::Abs16, 2),
+                    "abs8" => (RelocKind::Abs8, 1),
+                    "lo8" => (RelocKind::LowByte, 1),
+                    "hi8" => (RelocKind::HighByte, 1),
+                    "rel8" => (RelocKind::Rel8, 1),
+                    _ => return Err(format!("unknown relocation kind '{}'", kind)),
+                };
+                section.relocations.push(Relocation {
+                    offset: parse_offset(offset, width, size)?,
+                    kind,
+                    target: parse_expr(target)?,
+                    loc: parse_loc(files, file, line)?,
+                });
+            }
+            _ => {
+                let [offset, reserved, file, line, text] = split_fields(fields)?;
+                section.lines.push(LineInfo {
+                    offset: parse_offset(offset, 0, size)?,
+                    loc: SourceLoc {
+                        file: parse_file(files, file)?,
+                        line: parse_number(line)? as u32,
+                        text: text.into(),
+                    },
+                    reserved: reserved == "r",
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn finish_section(&mut self) {
+        if let Some(section) = self.section.take() {
+            let blob = CodeBlob::from_parts(
+                section.bytes,
+                section.symbols,
+                section.relocations,
+                section.lines,
+                section.has_contents,
+            );
+            self.object.sections.push((section.name, blob));
+        }
+    }
+}
+
+fn split_fields<const N: usize>(fields: &str) -> Result<[&str; N], String> {
+    // the last field takes the rest of the line, which may contain spaces
+    let parts: Vec<&str> = fields.splitn(N, ' ').collect();
+    parts
+        .try_into()
+        .map_err(|_| format!("expected {} fields", N))
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    text.parse()
+        .map_err(|_| format!("invalid number '{}'", text))
+}
+
+fn parse_file(files: &[String], text: &str) -> Result<Option<String>, String> {
+    if text == "-" {
+        return Ok(None);
+    }
+    match files.get(parse_number(text)?) {
+        Some(file) => Ok(Some(file.clone())),
+        None => Err(format!("unknown file {}", text)),
+    }
+}
+
+fn parse_loc(files: &[String], file: &str, line: &str) -> Result<SourceLoc, String> {
+    // the source text is only kept for line records
+    Ok(SourceLoc {
+        file: parse_file(files, file)?,
+        line: parse_number(line)? as u32,
+        text: String::new(),
+    })
+}
+
+fn parse_offset(text: &str, width: usize, size: usize) -> Result<u16, String> {
+    // offsets must leave room for the relocated bytes within the section
+    let offset = parse_number(text)?;
+    if offset + width > size {
+        return Err(format!("offset {} outside of the section", offset));
+    }
+    Ok(offset as u16)
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    AsmParser::parse_expr_text(text).ok_or_else(|| format!("invalid expression '{}'", text))
+}
@@ -11,25 +11,6 @@ pub fn get_opcode(mnemonic_i: usize, addr_mode_i: usize) -> Option<u8> {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::asm::model::{AddrMode, IndexMode, Instruction, MemRef};
-
-    #[test]
-    fn get_rel_opcode() {
-        let i = Instruction::new("beq".into(),
-        AddrMode::Memory(IndexMode::None, MemRef::Variable("test".into())));
-        assert_eq!(super::get_opcode(i.mnemonic_index(), 2).unwrap(), 0xf0);
-    }
-
-    #[test]
-    fn get_lda_opcode() {
-        let i = Instruction::new("lda".into(),
-            AddrMode::Memory(IndexMode::IndexedX, MemRef::Addr(0x1234)));
-        assert_eq!(super::get_opcode(i.mnemonic_index(), 9).unwrap(), 0xbd);
-    }
-}
-
 const OPCODE_TABLE: [[i16; 14]; 98] = [
     //  IMPL   IMM    ZP  zp,X  zp,Y  (zp)(zp,X)(zp),Y   abs abs,X abs,Y (abs)(abs,X)  rel
     [ 0x00,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1 ], // BRK
@@ -131,3 +112,22 @@ const OPCODE_TABLE: [[i16; 14]; 98] = [
     [ 0xfa,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1 ], // PLX
     [   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1,   -1, 0xff ], // BBS7
 ];
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::model::{AddrMode, Expr, IndexMode, Instruction};
+
+    #[test]
+    fn get_rel_opcode() {
+        let i = Instruction::new("beq".into(),
+        AddrMode::Memory(IndexMode::None, Expr::symbol("test")));
+        assert_eq!(super::get_opcode(i.mnemonic_index(), 2).unwrap(), 0xf0);
+    }
+
+    #[test]
+    fn get_lda_opcode() {
+        let i = Instruction::new("lda".into(),
+            AddrMode::Memory(IndexMode::IndexedX, Expr::Number(0x1234)));
+        assert_eq!(super::get_opcode(i.mnemonic_index(), 9).unwrap(), 0xbd);
+    }
+}
@@ -0,0 +1,72 @@
+use std::{fmt::Write, str::FromStr};
+
+/// File formats symbols can be exported in for debuggers and monitors. Each
+/// name appears only once: local symbols defined by more than one object
+/// file, or shadowing a global, are left out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolFormat {
+    /// VICE monitor labels, `al C:e000 .reset`
+    Vice,
+    /// `reset = $e000`, which can also be included into assembly source, as
+    /// long as it doesn't define the same symbols
+    Text,
+    /// JSON object with a list of name and value pairs
+    Json,
+}
+
+impl FromStr for SymbolFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vice" => Ok(SymbolFormat::Vice),
+            "txt" => Ok(SymbolFormat::Text),
+            "json" => Ok(SymbolFormat::Json),
+            _ => Err(format!("unknown symbol format '{}'", s)),
+        }
+    }
+}
+
+pub fn write(symbols: &[(&str, u16)], format: SymbolFormat) -> String {
+    let mut output = String::new();
+    match format {
+        SymbolFormat::Vice => {
+            for (name, value) in symbols {
+                writeln!(output, "al C:{:04x} .{}", value, name).unwrap();
+            }
+        }
+        SymbolFormat::Text => {
+            for (name, value) in symbols {
+                writeln!(output, "{} = ${:04x}", name, value).unwrap();
+            }
+        }
+        SymbolFormat::Json => {
+            output.push_str("{\n  \"symbols\": [");
+            for (i, (name, value)) in symbols.iter().enumerate() {
+                let separator = if i == 0 { "" } else { "," };
+                write!(
+                    output,
+                    "{}\n    {{\"name\": \"{}\", \"value\": {}}}",
+                    separator,
+                    json_escape(name),
+                    value
+                )
+                .unwrap();
+            }
+            output.push_str("\n  ]\n}\n");
+        }
+    }
+    output
+}
+
+fn json_escape(text: &str) -> String {
+    // symbol names are identifiers, but linker defined names are built from
+    // section names, so be safe
+    text.chars()
+        .map(|c| match c {
+            '"' | '\\' => format!("\\{}", c),
+            c if c.is_control() => format!("\\u{:04x}", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
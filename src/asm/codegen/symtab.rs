@@ -2,22 +2,25 @@ use std::collections::HashMap;
 
 pub struct SymbolTable {
     symbols: HashMap<String, u16>,
+    // whether the pseudo registers r0..r31 are known
+    registers: bool,
 }
 
 impl SymbolTable {
     pub fn new() -> SymbolTable {
         SymbolTable {
             symbols: HashMap::new(),
+            registers: false,
         }
     }
 
     pub fn new_with_registers() -> SymbolTable {
-        let mut table = SymbolTable::new();
-        for i in 0..32 {
-            // insert pseudo register symbols r0..r31
-            table.insert(&format!("r{}", i), i);
+        // the pseudo registers are looked up on demand rather than inserted,
+        // so they don't show up when iterating over the defined symbols.
+        SymbolTable {
+            registers: true,
+            ..SymbolTable::new()
         }
-        table
     }
 
     pub fn insert(&mut self, name: &str, value: u16) {
@@ -27,16 +30,47 @@ impl SymbolTable {
     pub fn insert_table(&mut self, table: &SymbolTable, offset: u16) {
         // merge with another symbol table object
         for (name, addr) in table.symbols.iter() {
-            self.symbols.insert(name.into(), addr + offset);
+            self.symbols.insert(name.into(), addr.wrapping_add(offset));
         }
     }
 
     pub fn find(&self, name: &str) -> Option<u16> {
-        match self.symbols.get(name.into()) {
-            Some(addr) => Some(*addr),
+        match self.symbols.get(name) {
+            Some(value) => Some(*value),
+            None if self.registers => register_number(name),
             None => None,
         }
     }
+
+    /// Like `find`, but ignores the pseudo registers
+    pub fn find_defined(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).copied()
+    }
+
+    /// All symbols except the pseudo registers, sorted by name
+    pub fn sorted(&self) -> Vec<(&str, u16)> {
+        let mut symbols: Vec<(&str, u16)> = self
+            .symbols
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        symbols.sort();
+        symbols
+    }
+}
+
+/// Whether the name is one of the pseudo registers r0..r31
+pub fn is_register(name: &str) -> bool {
+    register_number(name).is_some()
+}
+
+fn register_number(name: &str) -> Option<u16> {
+    // r0..r31 without leading zeros
+    let digits = name.strip_prefix('r')?;
+    if digits.len() > 1 && digits.starts_with('0') {
+        return None;
+    }
+    digits.parse().ok().filter(|number| *number < 32)
 }
 
 #[test]
@@ -64,4 +98,9 @@ fn symtab_pseudo_registers() {
 
     assert_eq!(symbols1.find("r14"), None);
     assert_eq!(symbols2.find("r14"), Some(14));
+    assert_eq!(symbols2.find("r32"), None);
+    assert_eq!(symbols2.find("r01"), None);
+    assert!(symbols2.sorted().is_empty());
+    assert_eq!(symbols2.find_defined("r14"), None);
+    assert!(is_register("r14") && !is_register("r32"));
 }
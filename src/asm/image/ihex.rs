@@ -0,0 +1,47 @@
+use super::Image;
+use std::fmt::Write;
+
+// data bytes per record, the value most tools emit
+const RECORD_SIZE: usize = 16;
+
+const DATA_RECORD: u8 = 0x00;
+const END_OF_FILE_RECORD: u8 = 0x01;
+
+/// Writes a data record for every chunk of up to 16 bytes of each segment,
+/// ordered by address, followed by the end of file record. All addresses
+/// fit into 16 bits, so no extended address records are needed.
+pub fn write(image: &Image) -> String {
+    let mut segments: Vec<_> = image.segments().iter().collect();
+    segments.sort_by_key(|segment| segment.addr());
+
+    let mut hex = String::new();
+    for segment in segments {
+        let mut addr = segment.addr();
+        for chunk in segment.data().chunks(RECORD_SIZE) {
+            write_record(&mut hex, addr, DATA_RECORD, chunk);
+            addr = addr.wrapping_add(chunk.len() as u16);
+        }
+    }
+    write_record(&mut hex, 0, END_OF_FILE_RECORD, &[]);
+    hex
+}
+
+fn write_record(hex: &mut String, addr: u16, record_type: u8, data: &[u8]) {
+    // :LLAAAATT<data>CC, the checksum is the two's complement of the sum of
+    // all preceding bytes of the record
+    let mut record = vec![data.len() as u8];
+    record.extend_from_slice(&addr.to_be_bytes());
+    record.push(record_type);
+    record.extend_from_slice(data);
+    let checksum = record
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+    record.push(checksum);
+
+    hex.push(':');
+    for byte in record {
+        write!(hex, "{:02X}", byte).unwrap();
+    }
+    hex.push('\n');
+}
@@ -0,0 +1,49 @@
+use super::Image;
+
+#[test]
+fn binary_fills_gaps() {
+    let mut image = Image::new();
+    image.add_segment(0x1004, vec![3, 4]);
+    image.add_segment(0x1000, vec![1, 2]);
+
+    assert_eq!(image.to_binary(), vec![1, 2, 0, 0, 3, 4]);
+    assert_eq!(Image::new().to_binary(), vec![]);
+}
+
+#[test]
+fn intel_hex_records() {
+    let mut image = Image::new();
+    image.add_segment(0xfffc, vec![0x00, 0xe0, 0x00, 0xe0]);
+    image.add_segment(0xe000, (0..18).collect());
+
+    assert_eq!(
+        image.to_intel_hex(),
+        "\
+:10E00000000102030405060708090A0B0C0D0E0F98
+:02E010001011ED
+:04FFFC0000E000E041
+:00000001FF
+"
+    );
+    assert_eq!(Image::new().to_intel_hex(), ":00000001FF\n");
+}
+
+#[test]
+fn srecord_records() {
+    let mut image = Image::new();
+    image.add_segment(0xfffc, vec![0x00, 0xe0]);
+    image.add_segment(0xe000, (0..18).collect());
+    image.set_entry(0xe000);
+
+    assert_eq!(
+        image.to_srecord("rom"),
+        "\
+S0060000726F6DAB
+S113E000000102030405060708090A0B0C0D0E0F94
+S105E0101011E9
+S105FFFC00E01F
+S903E0001C
+"
+    );
+    assert_eq!(Image::new().to_srecord(""), "S0030000FC\nS9030000FC\n");
+}
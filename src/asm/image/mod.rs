@@ -0,0 +1,89 @@
+mod ihex;
+mod srec;
+
+#[cfg(test)]
+mod image_tests;
+
+/// Output of the linker: the contents of every placed section together
+/// with the address it has to be loaded to.
+#[derive(Debug, Default, PartialEq)]
+pub struct Image {
+    segments: Vec<Segment>,
+    entry: Option<u16>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Segment {
+    addr: u16,
+    data: Vec<u8>,
+}
+
+impl Image {
+    pub fn new() -> Image {
+        Image {
+            segments: vec![],
+            entry: None,
+        }
+    }
+
+    pub fn add_segment(&mut self, addr: u16, data: Vec<u8>) {
+        self.segments.push(Segment { addr, data });
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Address execution starts at, for output formats that record one
+    pub fn set_entry(&mut self, addr: u16) {
+        self.entry = Some(addr);
+    }
+
+    pub fn entry(&self) -> Option<u16> {
+        self.entry
+    }
+
+    /// Intel HEX records for the occupied address ranges only, gaps between
+    /// segments are skipped.
+    pub fn to_intel_hex(&self) -> String {
+        ihex::write(self)
+    }
+
+    /// Motorola S-records (S19): an S0 header record containing `header`,
+    /// S1 data records for the occupied address ranges and an S9 record
+    /// with the entry point, which is 0 if none is set.
+    pub fn to_srecord(&self, header: &str) -> String {
+        srec::write(self, header)
+    }
+
+    /// Flat binary from the lowest to the highest occupied address. Gaps
+    /// between segments are filled with zeros.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let start = match self.segments.iter().map(|s| s.addr).min() {
+            Some(start) => start as usize,
+            None => return vec![],
+        };
+        let end = self.segments.iter().map(|s| s.end()).max().unwrap();
+
+        let mut binary = vec![0u8; end - start];
+        for segment in self.segments.iter() {
+            let offset = segment.addr as usize - start;
+            binary[offset..offset + segment.data.len()].copy_from_slice(&segment.data);
+        }
+        binary
+    }
+}
+
+impl Segment {
+    pub fn addr(&self) -> u16 {
+        self.addr
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn end(&self) -> usize {
+        self.addr as usize + self.data.len()
+    }
+}
@@ -0,0 +1,43 @@
+use super::Image;
+use std::fmt::Write;
+
+// data bytes per S1 record
+const RECORD_SIZE: usize = 16;
+
+// the byte count field covers the address, the data and the checksum
+const MAX_HEADER_SIZE: usize = 255 - 3;
+
+pub fn write(image: &Image, header: &str) -> String {
+    let mut segments: Vec<_> = image.segments().iter().collect();
+    segments.sort_by_key(|segment| segment.addr());
+
+    let mut srec = String::new();
+    let header = &header.as_bytes()[..header.len().min(MAX_HEADER_SIZE)];
+    write_record(&mut srec, '0', 0, header);
+    for segment in segments {
+        let mut addr = segment.addr();
+        for chunk in segment.data().chunks(RECORD_SIZE) {
+            write_record(&mut srec, '1', addr, chunk);
+            addr = addr.wrapping_add(chunk.len() as u16);
+        }
+    }
+    write_record(&mut srec, '9', image.entry().unwrap_or(0), &[]);
+    srec
+}
+
+fn write_record(srec: &mut String, record_type: char, addr: u16, data: &[u8]) {
+    // S<type><count><addr><data><checksum>, the checksum is the ones'
+    // complement of the sum of all bytes from the count on
+    let mut record = vec![(data.len() + 3) as u8];
+    record.extend_from_slice(&addr.to_be_bytes());
+    record.extend_from_slice(data);
+    let checksum = !record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    record.push(checksum);
+
+    srec.push('S');
+    srec.push(record_type);
+    for byte in record {
+        write!(srec, "{:02X}", byte).unwrap();
+    }
+    srec.push('\n');
+}
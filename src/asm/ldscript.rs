@@ -1,33 +1,178 @@
+use crate::errors::{CompileError, Diagnostic, Diagnostics, ErrorMessage};
 use logos::Logos;
+use std::fs;
 
-#[derive(Logos, PartialEq, Copy, Clone)]
+#[derive(Logos, Debug, PartialEq, Copy, Clone)]
 enum LdScriptToken {
-    #[regex(r".[A-Za-z_]+")]
+    #[regex(r"\.[A-Za-z_][A-Za-z0-9_]*")]
     SectionIdentifier,
 
-    #[regex(r"@0x[0-9A-Fa-f]+")]
-    Address,
+    #[regex(r"[A-Za-z_][A-Za-z0-9_]*")]
+    Identifier,
+
+    #[regex(r"0x[0-9A-Fa-f]+|\$[0-9A-Fa-f]+|[0-9]+")]
+    Number,
+
+    #[token("@")]
+    At,
+
+    #[token(">")]
+    Greater,
+
+    #[token(":")]
+    Colon,
+
+    #[token("=")]
+    Equals,
+
+    #[token("\n")]
+    Newline,
 
     // Logos requires one token variant to handle errors,
     // it can be named anything you wish.
     #[error]
     // We can also use this variant to define whitespace,
     // or any other matches we wish to skip.
-    #[regex(r"[ \t\f\n]+", logos::skip)]
+    #[regex(r"[ \t\f\r]+", logos::skip)]
+    #[regex(r"(#|//)[^\n]*", logos::skip)]
     Error,
 }
 
+#[derive(Debug)]
+pub enum LdScriptError {
+    UnexpectedToken(String),
+    AddressTooLarge(String),
+    DuplicateSection(String),
+    MissingStartAddress(String),
+    NoSections,
+    DuplicateRegion(String),
+    UnknownRegion(String),
+    UnknownRegionAttribute(String),
+    MissingRegionAttribute(String, &'static str),
+    RegionTooLarge(String),
+    NoLoadWithLoadAddress(String),
+}
+
+impl ErrorMessage for LdScriptError {
+    fn error_msg(&self) -> String {
+        match self {
+            LdScriptError::UnexpectedToken(s) => format!("unexpected token: '{}'", s),
+            LdScriptError::AddressTooLarge(s) => format!("address {} does not fit into 16 bits", s),
+            LdScriptError::DuplicateSection(s) => format!("section .{} is placed twice", s),
+            LdScriptError::MissingStartAddress(s) => {
+                format!("the first section .{} needs an address or a region", s)
+            }
+            LdScriptError::NoSections => "the linker script doesn't place any sections".into(),
+            LdScriptError::DuplicateRegion(s) => format!("region {} is declared twice", s),
+            LdScriptError::UnknownRegion(s) => format!("unknown region {}", s),
+            LdScriptError::UnknownRegionAttribute(s) => {
+                format!("unknown region attribute '{}', use start or size", s)
+            }
+            LdScriptError::MissingRegionAttribute(s, attr) => {
+                format!("region {} is missing the {} attribute", s, attr)
+            }
+            LdScriptError::RegionTooLarge(s) => {
+                format!("region {} extends beyond the address space", s)
+            }
+            LdScriptError::NoLoadWithLoadAddress(s) => {
+                format!("NOLOAD section .{} cannot have a load address", s)
+            }
+        }
+    }
+}
+
+/// Range of the address space that sections can be assigned to, declared
+/// as `ROM: start=$e000 size=$2000`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryRegion {
+    name: String,
+    start: u16,
+    size: u32,
+}
+
+impl MemoryRegion {
+    pub fn new(name: &str, start: u16, size: u32) -> MemoryRegion {
+        MemoryRegion {
+            name: name.into(),
+            start,
+            size,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// First address after the region
+    pub fn end(&self) -> u32 {
+        self.start as u32 + self.size
+    }
+}
+
+/// A section as placed by the linker script. Labels are resolved against
+/// the run address (VMA), while the section's bytes are stored at the load
+/// address (LMA), which defaults to the run address. Startup code has to
+/// copy sections with a different load address to their run address.
 #[derive(Debug, PartialEq)]
 pub struct LdSection {
     name: String,
+    addr: Option<u16>,
+    region: Option<String>,
     load_addr: Option<u16>,
+    load_region: Option<String>,
+    noload: bool,
 }
 
 impl LdSection {
-    pub fn new(name: &str, load_addr: Option<u16>) -> LdSection {
+    pub fn new(name: &str, addr: Option<u16>) -> LdSection {
         LdSection {
             name: name.into(),
-            load_addr,
+            addr,
+            region: None,
+            load_addr: None,
+            load_region: None,
+            noload: false,
+        }
+    }
+
+    /// Section placed at the next free address of the given region
+    pub fn in_region(name: &str, region: &str) -> LdSection {
+        LdSection {
+            region: Some(region.into()),
+            ..LdSection::new(name, None)
+        }
+    }
+
+    /// Stores the section's bytes at a fixed load address
+    pub fn with_load_addr(self, load_addr: u16) -> LdSection {
+        LdSection {
+            load_addr: Some(load_addr),
+            ..self
+        }
+    }
+
+    /// Stores the section's bytes at the next free address of a region
+    pub fn with_load_region(self, region: &str) -> LdSection {
+        LdSection {
+            load_region: Some(region.into()),
+            ..self
+        }
+    }
+
+    /// Only assigns addresses to the section, its bytes are not stored in
+    /// the image. Such sections may only reserve space with `.res`.
+    pub fn with_noload(self) -> LdSection {
+        LdSection {
+            noload: true,
+            ..self
         }
     }
 
@@ -35,89 +180,486 @@ impl LdSection {
         &self.name
     }
 
+    /// Fixed run address
+    pub fn addr(&self) -> Option<u16> {
+        self.addr
+    }
+
+    /// Region the section runs in
+    pub fn region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
     pub fn load_addr(&self) -> Option<u16> {
         self.load_addr
     }
+
+    pub fn load_region(&self) -> Option<&str> {
+        self.load_region.as_deref()
+    }
+
+    pub fn is_noload(&self) -> bool {
+        self.noload
+    }
 }
 
-#[cfg(test)]
-fn check_for_addr(next_token: Option<LdScriptToken>, slice: &str) -> Option<u16> {
-    if next_token? == LdScriptToken::Address {
-        let hex_addr = &slice[3..];
-        Some(u16::from_str_radix(hex_addr, 16).unwrap())
+/// Memory layout used by the linker: the sections in the order they are
+/// placed, and the memory regions they can be assigned to.
+#[derive(Debug, Default, PartialEq)]
+pub struct LdScript {
+    regions: Vec<MemoryRegion>,
+    sections: Vec<LdSection>,
+}
+
+impl LdScript {
+    pub fn new(sections: Vec<LdSection>) -> LdScript {
+        LdScript {
+            regions: vec![],
+            sections,
+        }
+    }
+
+    pub fn with_regions(regions: Vec<MemoryRegion>, sections: Vec<LdSection>) -> LdScript {
+        LdScript { regions, sections }
+    }
+
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions
+    }
+
+    pub fn sections(&self) -> &[LdSection] {
+        &self.sections
+    }
+
+    pub fn region(&self, name: &str) -> Option<&MemoryRegion> {
+        self.regions.iter().find(|region| region.name == name)
+    }
+}
+
+/// Reads and parses the linker script at `path`.
+pub fn load(path: &str) -> Result<LdScript, Diagnostics> {
+    let source = fs::read_to_string(path).map_err(|error| {
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(Diagnostic::in_file(path, &error.to_string()));
+        diagnostics
+    })?;
+
+    parse(&source).map_err(|errors| {
+        let mut diagnostics = Diagnostics::new();
+        for error in errors.iter() {
+            diagnostics.push(error.to_diagnostic(path));
+        }
+        diagnostics
+    })
+}
+
+/// Parses a linker script. Every line either declares a memory region or
+/// places a section:
+///
+/// ```text
+/// ROM: start=$e000 size=$2000
+/// .text > ROM
+/// .vectors @$fffa
+/// ```
+///
+/// Sections are placed at their address, at the next free address of their
+/// region or, if neither is given, right after the previous section. A
+/// separate load address can be given after `AT`: `.data > RAM AT > ROM`.
+pub fn parse(source: &str) -> Result<LdScript, Vec<CompileError<LdScriptError>>> {
+    let mut parser = ScriptParser {
+        lexer: LdScriptToken::lexer(source),
+        token: None,
+        line: 1,
+        errors: vec![],
+        region_refs: vec![],
+    };
+    let mut script = LdScript::default();
+
+    parser.next();
+    while let Some(token) = parser.token {
+        if token == LdScriptToken::Newline {
+            parser.line += 1;
+            parser.next();
+            continue;
+        }
+
+        // after an error, the rest of the line is skipped
+        parser.parse_line(&mut script);
+        while !matches!(parser.token, None | Some(LdScriptToken::Newline)) {
+            parser.next();
+        }
+    }
+
+    for (name, line) in parser.region_refs.iter() {
+        if script.region(name).is_none() {
+            parser.errors.push(CompileError::new(
+                LdScriptError::UnknownRegion(name.clone()),
+                *line,
+            ));
+        }
+    }
+    if script.sections.is_empty() && parser.errors.is_empty() {
+        parser.error(LdScriptError::NoSections);
+    }
+
+    if parser.errors.is_empty() {
+        Ok(script)
     } else {
-        None
+        Err(parser.errors)
     }
 }
 
-#[cfg(test)]
-pub fn parse(source: &str) -> Result<Vec<LdSection>, String> {
-    let mut lexer = LdScriptToken::lexer(source);
-    let mut sections = vec![];
-    let mut current_token = lexer.next();
-    loop {
-        if let Some(token) = current_token {
-            match token {
-                LdScriptToken::SectionIdentifier => {
-                    let name: String = (&lexer.slice()[1..]).into();
-                    current_token = lexer.next();
-                    let load_addr = check_for_addr(current_token, lexer.slice());
-                    if load_addr.is_some() {
-                        current_token = lexer.next();
-                    }
-                    sections.push(LdSection { name, load_addr });
+struct ScriptParser<'a> {
+    lexer: logos::Lexer<'a, LdScriptToken>,
+    token: Option<LdScriptToken>,
+    line: u32,
+    errors: Vec<CompileError<LdScriptError>>,
+    // regions referenced by sections, checked once all regions are known
+    region_refs: Vec<(String, u32)>,
+}
+
+impl<'a> ScriptParser<'a> {
+    fn next(&mut self) {
+        self.token = self.lexer.next();
+    }
+
+    fn error(&mut self, error: LdScriptError) {
+        self.errors.push(CompileError::new(error, self.line));
+    }
+
+    fn unexpected_token(&mut self) -> Option<()> {
+        let slice = match self.token {
+            Some(LdScriptToken::Newline) | None => "end of line",
+            Some(_) => self.lexer.slice(),
+        };
+        self.error(LdScriptError::UnexpectedToken(slice.into()));
+        None
+    }
+
+    fn expect(&mut self, expected: LdScriptToken) -> Option<()> {
+        if self.token != Some(expected) {
+            return self.unexpected_token();
+        }
+        self.next();
+        Some(())
+    }
+
+    /// Parses the remainder of a line. When done, the current token is the
+    /// newline (or the end of the script), unless there are excess tokens.
+    fn parse_line(&mut self, script: &mut LdScript) -> Option<()> {
+        match self.token {
+            Some(LdScriptToken::SectionIdentifier) => {
+                let section = self.parse_section()?;
+                if script.sections.iter().any(|s| s.name == section.name) {
+                    self.error(LdScriptError::DuplicateSection(section.name));
+                    return None;
+                }
+                if script.sections.is_empty() && section.addr.is_none() && section.region.is_none()
+                {
+                    self.error(LdScriptError::MissingStartAddress(section.name));
+                    return None;
+                }
+                script.sections.push(section);
+            }
+            Some(LdScriptToken::Identifier) => {
+                let region = self.parse_region()?;
+                if script.region(&region.name).is_some() {
+                    self.error(LdScriptError::DuplicateRegion(region.name));
+                    return None;
                 }
+                script.regions.push(region);
+            }
+            _ => return self.unexpected_token(),
+        }
+
+        match self.token {
+            None | Some(LdScriptToken::Newline) => Some(()),
+            _ => self.unexpected_token(),
+        }
+    }
+
+    fn parse_section(&mut self) -> Option<LdSection> {
+        // .name [@addr] [> REGION] [AT [@addr] [> REGION]] [NOLOAD]
+        let mut section = LdSection::new(&self.lexer.slice()[1..], None);
+        self.next();
+        (section.addr, section.region) = self.parse_location()?;
+
+        let is_at_keyword =
+            self.token == Some(LdScriptToken::Identifier) && self.lexer.slice() == "AT";
+        if is_at_keyword {
+            self.next();
+            (section.load_addr, section.load_region) = self.parse_location()?;
+            if section.load_addr.is_none() && section.load_region.is_none() {
+                self.unexpected_token();
+                return None;
+            }
+        }
+
+        let is_noload_keyword =
+            self.token == Some(LdScriptToken::Identifier) && self.lexer.slice() == "NOLOAD";
+        if is_noload_keyword {
+            if is_at_keyword {
+                self.error(LdScriptError::NoLoadWithLoadAddress(section.name.clone()));
+                return None;
+            }
+            self.next();
+            section.noload = true;
+        }
+        Some(section)
+    }
+
+    fn parse_location(&mut self) -> Option<(Option<u16>, Option<String>)> {
+        let mut addr = None;
+        if self.token == Some(LdScriptToken::At) {
+            self.next();
+            addr = Some(self.parse_addr()?);
+        }
+
+        let mut region = None;
+        if self.token == Some(LdScriptToken::Greater) {
+            self.next();
+            if self.token != Some(LdScriptToken::Identifier) {
+                self.unexpected_token();
+                return None;
+            }
+            let name: String = self.lexer.slice().into();
+            self.region_refs.push((name.clone(), self.line));
+            region = Some(name);
+            self.next();
+        }
+        Some((addr, region))
+    }
+
+    fn parse_region(&mut self) -> Option<MemoryRegion> {
+        // NAME: start=<addr> size=<size>
+        let name: String = self.lexer.slice().into();
+        self.next();
+        self.expect(LdScriptToken::Colon)?;
+
+        let mut start = None;
+        let mut size = None;
+        while self.token == Some(LdScriptToken::Identifier) {
+            let attribute: String = self.lexer.slice().into();
+            self.next();
+            self.expect(LdScriptToken::Equals)?;
+            match attribute.as_ref() {
+                "start" => start = Some(self.parse_addr()?),
+                "size" => size = Some(self.parse_number()?),
                 _ => {
-                    return Err(format!("unexpected token: '{}'", lexer.slice()));
+                    self.error(LdScriptError::UnknownRegionAttribute(attribute));
+                    return None;
                 }
             }
+        }
+
+        let region = match (start, size) {
+            (Some(start), Some(size)) => MemoryRegion::new(&name, start, size),
+            (None, _) => {
+                self.error(LdScriptError::MissingRegionAttribute(name, "start"));
+                return None;
+            }
+            (_, None) => {
+                self.error(LdScriptError::MissingRegionAttribute(name, "size"));
+                return None;
+            }
+        };
+        if region.end() > 0x10000 {
+            self.error(LdScriptError::RegionTooLarge(name));
+            return None;
+        }
+        Some(region)
+    }
+
+    fn parse_addr(&mut self) -> Option<u16> {
+        let slice: String = self.lexer.slice().into();
+        let value = self.parse_number()?;
+        if value > 0xffff {
+            self.error(LdScriptError::AddressTooLarge(slice));
+            return None;
+        }
+        Some(value as u16)
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        if self.token != Some(LdScriptToken::Number) {
+            self.unexpected_token();
+            return None;
+        }
+        let slice = self.lexer.slice();
+        let value = if let Some(hex) = slice.strip_prefix("0x").or_else(|| slice.strip_prefix('$'))
+        {
+            u32::from_str_radix(hex, 16)
         } else {
-            break;
+            slice.parse::<u32>()
+        };
+        match value {
+            Ok(value) => {
+                self.next();
+                Some(value)
+            }
+            Err(_) => {
+                self.error(LdScriptError::AddressTooLarge(slice.into()));
+                None
+            }
         }
     }
-    Ok(sections)
+}
+
+#[cfg(test)]
+fn error_lines(source: &str) -> Vec<(u32, String)> {
+    parse(source)
+        .unwrap_err()
+        .iter()
+        .map(|error| {
+            let diagnostic = error.to_diagnostic("ld");
+            (diagnostic.line().unwrap(), diagnostic.message().into())
+        })
+        .collect()
 }
 
 #[test]
 fn linker_script_parser_test() {
-    let sections = parse(
+    let script = parse(
         r#"
         .text @0xe000
+        # sections without address follow the previous one
         .data
-        .vectors @0xfffa
-    "#
-        .into(),
+        .vectors @$fffa // reset and interrupt vectors
+        .zp @0
+    "#,
     )
     .unwrap();
     assert_eq!(
-        sections,
+        script.sections(),
         vec![
+            LdSection::new("text", Some(0xe000)),
+            LdSection::new("data", None),
+            LdSection::new("vectors", Some(0xfffa)),
+            LdSection::new("zp", Some(0)),
+        ]
+    );
+    assert!(script.regions().is_empty());
+}
+
+#[test]
+fn linker_script_regions_test() {
+    let script = parse(
+        r#"
+        ROM: start=$e000 size=$2000
+        RAM: size=32256 start=0x0200
+        .text > ROM
+        .data > RAM
+        .vectors @$fffa > ROM
+    "#,
+    )
+    .unwrap();
+    assert_eq!(
+        script.regions(),
+        vec![
+            MemoryRegion::new("ROM", 0xe000, 0x2000),
+            MemoryRegion::new("RAM", 0x0200, 0x7e00),
+        ]
+    );
+    assert_eq!(
+        script.sections(),
+        vec![
+            LdSection::in_region("text", "ROM"),
+            LdSection::in_region("data", "RAM"),
             LdSection {
-                name: "text".into(),
-                load_addr: Some(0xe000),
-            },
-            LdSection {
-                name: "data".into(),
-                load_addr: None,
+                region: Some("ROM".into()),
+                ..LdSection::new("vectors", Some(0xfffa))
             },
-            LdSection {
-                name: "vectors".into(),
-                load_addr: Some(0xfffa),
-            }
+        ]
+    );
+}
+
+#[test]
+fn linker_script_load_addr_test() {
+    let script = parse(
+        r#"
+        ROM: start=$e000 size=$2000
+        RAM: start=$0200 size=$7e00
+        .text > ROM
+        .data > RAM AT > ROM
+        .fast @$0080 AT @$f000
+        .bss > RAM NOLOAD
+    "#,
+    )
+    .unwrap();
+    assert_eq!(
+        script.sections(),
+        vec![
+            LdSection::in_region("text", "ROM"),
+            LdSection::in_region("data", "RAM").with_load_region("ROM"),
+            LdSection::new("fast", Some(0x80)).with_load_addr(0xf000),
+            LdSection::in_region("bss", "RAM").with_noload(),
         ]
     );
 }
 
 #[test]
 fn linker_script_error_test() {
-    let sections = parse(
+    let errors = error_lines(
         r#"
         @0x0001
+        .text @0x8000
+        .data @0x10000
         .text
-        .data
-        .vectors @0xfffa
-    "#
-        .into(),
+        .bss @$100 .more
+        .vectors @$fffa AT
+        .bss @$0200 AT @$e000 NOLOAD
+    "#,
+    );
+    assert_eq!(
+        errors,
+        vec![
+            (2, "unexpected token: '@'".into()),
+            (4, "address 0x10000 does not fit into 16 bits".into()),
+            (5, "section .text is placed twice".into()),
+            (6, "unexpected token: '.more'".into()),
+            (7, "unexpected token: 'end of line'".into()),
+            (8, "NOLOAD section .bss cannot have a load address".into()),
+        ]
+    );
+
+    assert_eq!(
+        error_lines(".text\n.data @0x200\n"),
+        vec![(
+            1,
+            "the first section .text needs an address or a region".into()
+        )]
+    );
+    assert_eq!(
+        error_lines("// nothing here\n"),
+        vec![(2, "the linker script doesn't place any sections".into())]
+    );
+}
+
+#[test]
+fn linker_script_region_error_test() {
+    let errors = error_lines(
+        r#"
+        ROM: start=$e000 size=$2001
+        RAM: start=$0200
+        IO: start=$8000 size=$100 kind=io
+        ZP: start=0 size=$100
+        ZP: start=0 size=$100
+        .text > FLASH
+        .data >
+    "#,
+    );
+    assert_eq!(
+        errors,
+        vec![
+            (2, "region ROM extends beyond the address space".into()),
+            (3, "region RAM is missing the size attribute".into()),
+            (
+                4,
+                "unknown region attribute 'kind', use start or size".into()
+            ),
+            (6, "region ZP is declared twice".into()),
+            (8, "unexpected token: 'end of line'".into()),
+            (7, "unknown region FLASH".into()),
+        ]
     );
-    assert_eq!(sections, Err("unexpected token: '@0x0001'".into()));
 }
@@ -1,37 +1,71 @@
 use super::AsmToken;
 use logos::Logos;
 
+/// Selects the meaning of `;` in assembly source. `//` line comments and
+/// `/* */` block comments are available in either dialect.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Dialect {
+    /// `;` separates multiple statements on the same line
+    #[default]
+    SemicolonSeparator,
+    /// `;` starts a comment that extends to the end of the line
+    SemicolonComment,
+}
+
 pub struct AsmLexer<'a> {
     lexer: logos::Lexer<'a, AsmToken>,
     current_token: AsmToken,
     line: u32,
+    dialect: Dialect,
 }
 
 impl<'a> AsmLexer<'a> {
-    pub fn new(source: &'a str) -> AsmLexer {
+    pub fn new(source: &'a str, dialect: Dialect) -> AsmLexer<'a> {
         AsmLexer {
             lexer: AsmToken::lexer(source),
             current_token: AsmToken::Error,
             line: 1,
+            dialect,
         }
     }
 
+    /// Value of a numeric or character literal. Returns `None` if the current
+    /// token isn't one or if the literal doesn't denote a valid value, e.g.
+    /// because it overflows or a character literal holds more than one byte.
     pub fn numeric_value(&self) -> Option<u64> {
-        let mut number_str = self.lexer.slice();
+        let literal = self.lexer.slice();
+        let (digits, radix) = match self.current_token {
+            AsmToken::HexInteger => (strip_radix_prefix(literal, '$', "0x"), 16),
+            AsmToken::DecInteger => (literal, 10),
+            AsmToken::BinInteger => (strip_radix_prefix(literal, '%', "0b"), 2),
+            AsmToken::OctInteger => (&literal[2..], 8),
+            AsmToken::CharLiteral => {
+                return match unescape(&literal[1..literal.len() - 1]) {
+                    Ok(bytes) if bytes.len() == 1 => Some(bytes[0] as u64),
+                    _ => None,
+                };
+            }
+            _ => return None,
+        };
+        u64::from_str_radix(&digits.replace('_', ""), radix).ok()
+    }
+
+    /// Contents of a string literal with all escape sequences replaced. On
+    /// failure, the offending escape sequence is returned.
+    pub fn string_value(&self) -> Option<Result<Vec<u8>, String>> {
         match self.current_token {
-            AsmToken::HexInteger => {
-                if number_str.chars().next().unwrap() == '$' {
-                    number_str = &number_str[1..];
-                } else {
-                    number_str = &number_str[2..];
-                }
-                Some(u64::from_str_radix(number_str, 16).unwrap())
+            AsmToken::StringLiteral => {
+                let literal = self.lexer.slice();
+                Some(unescape(&literal[1..literal.len() - 1]))
             }
-            AsmToken::DecInteger => Some(u64::from_str_radix(&number_str, 10).unwrap()),
             _ => None,
         }
     }
 
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
     pub fn line(&self) -> u32 {
         self.line
     }
@@ -45,14 +79,65 @@ impl<'a> AsmLexer<'a> {
     }
 
     pub fn next_token(&mut self) -> AsmToken {
-        let token = match self.lexer.next() {
-            Some(token) => token,
-            None => AsmToken::End,
-        };
         if self.current_token == AsmToken::Newline {
             self.line += 1;
         }
+
+        let token = loop {
+            match self.lexer.next() {
+                Some(AsmToken::BlockComment) => {
+                    self.line += self.lexer.slice().matches('\n').count() as u32;
+                }
+                Some(AsmToken::Semicolon) if self.dialect == Dialect::SemicolonComment => {
+                    // skip the comment, but not the newline terminating it
+                    let remainder = self.lexer.remainder();
+                    self.lexer
+                        .bump(remainder.find('\n').unwrap_or(remainder.len()));
+                }
+                Some(token) => break token,
+                None => break AsmToken::End,
+            }
+        };
         self.current_token = token.clone();
         token
     }
 }
+
+fn strip_radix_prefix<'a>(literal: &'a str, short: char, long: &str) -> &'a str {
+    literal
+        .strip_prefix(short)
+        .or_else(|| literal.strip_prefix(long))
+        .unwrap_or(literal)
+}
+
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        let escape = chars.next().unwrap_or_default();
+        bytes.push(match escape {
+            'n' => b'\n',
+            'r' => b'\r',
+            't' => b'\t',
+            '0' => 0x00,
+            '"' => b'"',
+            '\'' => b'\'',
+            '\\' => b'\\',
+            'x' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(value) if digits.len() == 2 => value,
+                    _ => return Err(format!("\\x{}", digits)),
+                }
+            }
+            _ => return Err(format!("\\{}", escape)),
+        });
+    }
+    Ok(bytes)
+}
@@ -1,8 +1,8 @@
-use super::{AsmLexer, AsmToken};
+use super::{AsmLexer, AsmToken, Dialect};
 
 #[test]
 fn asm_lexer_hex_literals() {
-    let mut lex = AsmLexer::new("$32 $fff0 $deadbeef 0x0a3 0xF3");
+    let mut lex = AsmLexer::new("$32 $fff0 $deadbeef 0x0a3 0xF3", Dialect::default());
 
     let values: Vec<u64> = vec![0x32, 0xfff0, 0xdeadbeef, 0xa3, 0xf3];
     for value in values.iter() {
@@ -13,7 +13,7 @@ fn asm_lexer_hex_literals() {
 
 #[test]
 fn asm_lexer_dec_literals() {
-    let mut lex = AsmLexer::new("284 290 91");
+    let mut lex = AsmLexer::new("284 290 91", Dialect::default());
 
     let values: Vec<u64> = vec![284, 290, 91];
     for value in values.iter() {
@@ -21,3 +21,163 @@ fn asm_lexer_dec_literals() {
         assert_eq!(*value, lex.numeric_value().unwrap());
     }
 }
+
+#[test]
+fn asm_lexer_comments() {
+    let mut lex = AsmLexer::new(
+        "lda // load\n/* multi\nline\ncomment */ rts /**/;/* a ** b */\n",
+        Dialect::default(),
+    );
+
+    let tokens = vec![
+        (AsmToken::Identifier, 1),
+        (AsmToken::Newline, 1),
+        (AsmToken::Identifier, 4),
+        (AsmToken::Semicolon, 4),
+        (AsmToken::Newline, 4),
+        (AsmToken::End, 5),
+    ];
+    for (token, line) in tokens.into_iter() {
+        assert_eq!(token, lex.next_token());
+        assert_eq!(line, lex.line());
+    }
+}
+
+#[test]
+fn asm_lexer_semicolon_comments() {
+    let mut lex = AsmLexer::new("inc ; inx /*\n  dec ; */ dex\n", Dialect::SemicolonComment);
+
+    let tokens = vec![
+        (AsmToken::Identifier, 1),
+        (AsmToken::Newline, 1),
+        (AsmToken::Identifier, 2),
+        (AsmToken::Newline, 2),
+        (AsmToken::End, 3),
+    ];
+    for (token, line) in tokens.into_iter() {
+        assert_eq!(token, lex.next_token());
+        assert_eq!(line, lex.line());
+    }
+}
+
+#[test]
+fn asm_lexer_unterminated_block_comment() {
+    let mut lex = AsmLexer::new("lda /* comment\n", Dialect::default());
+
+    assert_eq!(AsmToken::Identifier, lex.next_token());
+    assert_eq!(AsmToken::Error, lex.next_token());
+    assert_eq!(AsmToken::End, lex.next_token());
+}
+
+#[test]
+fn asm_lexer_string_escapes() {
+    let mut lex = AsmLexer::new(
+        r#""plain" "a\n\r\t\0" "\"q\" \\" "\x41\xff" "\q" "\x4""#,
+        Dialect::default(),
+    );
+
+    let values: Vec<Result<Vec<u8>, String>> = vec![
+        Ok(b"plain".to_vec()),
+        Ok(b"a\n\r\t\0".to_vec()),
+        Ok(b"\"q\" \\".to_vec()),
+        Ok(vec![0x41, 0xff]),
+        Err(r"\q".into()),
+        Err(r"\x4".into()),
+    ];
+    for value in values.into_iter() {
+        assert_eq!(AsmToken::StringLiteral, lex.next_token());
+        assert_eq!(value, lex.string_value().unwrap());
+    }
+    assert_eq!(AsmToken::End, lex.next_token());
+}
+
+#[test]
+fn asm_lexer_zero_literals() {
+    let mut lex = AsmLexer::new("0 $0 0x00 %0 0b0 0o0", Dialect::default());
+
+    let tokens = vec![
+        AsmToken::DecInteger,
+        AsmToken::HexInteger,
+        AsmToken::HexInteger,
+        AsmToken::BinInteger,
+        AsmToken::BinInteger,
+        AsmToken::OctInteger,
+    ];
+    for token in tokens.into_iter() {
+        assert_eq!(token, lex.next_token());
+        assert_eq!(0, lex.numeric_value().unwrap());
+    }
+}
+
+#[test]
+fn asm_lexer_bin_literals() {
+    let mut lex = AsmLexer::new("%01010101 %1 0b0101 0b1111_0000", Dialect::default());
+
+    let values: Vec<u64> = vec![0x55, 1, 5, 0xf0];
+    for value in values.iter() {
+        assert_eq!(AsmToken::BinInteger, lex.next_token());
+        assert_eq!(*value, lex.numeric_value().unwrap());
+    }
+}
+
+#[test]
+fn asm_lexer_oct_literals() {
+    let mut lex = AsmLexer::new("0o17 0o777 0o1_0", Dialect::default());
+
+    let values: Vec<u64> = vec![0o17, 0o777, 0o10];
+    for value in values.iter() {
+        assert_eq!(AsmToken::OctInteger, lex.next_token());
+        assert_eq!(*value, lex.numeric_value().unwrap());
+    }
+}
+
+#[test]
+fn asm_lexer_digit_separators() {
+    let mut lex = AsmLexer::new("1_000 $ff_00 0xde_ad 65_535", Dialect::default());
+
+    let values: Vec<(AsmToken, u64)> = vec![
+        (AsmToken::DecInteger, 1000),
+        (AsmToken::HexInteger, 0xff00),
+        (AsmToken::HexInteger, 0xdead),
+        (AsmToken::DecInteger, 65535),
+    ];
+    for (token, value) in values.into_iter() {
+        assert_eq!(token, lex.next_token());
+        assert_eq!(value, lex.numeric_value().unwrap());
+    }
+}
+
+#[test]
+fn asm_lexer_char_literals() {
+    let mut lex = AsmLexer::new(
+        r"'A' 'z' ' ' '\n' '\'' '\x7f' 'ab' '\q'",
+        Dialect::default(),
+    );
+
+    let values: Vec<Option<u64>> = vec![
+        Some(0x41),
+        Some(0x7a),
+        Some(0x20),
+        Some(0x0a),
+        Some(0x27),
+        Some(0x7f),
+        None,
+        None,
+    ];
+    for value in values.into_iter() {
+        assert_eq!(AsmToken::CharLiteral, lex.next_token());
+        assert_eq!(value, lex.numeric_value());
+    }
+}
+
+#[test]
+fn asm_lexer_modulo_and_bin_prefix() {
+    let mut lex = AsmLexer::new("a % 10 %10", Dialect::default());
+
+    assert_eq!(AsmToken::Identifier, lex.next_token());
+    assert_eq!(AsmToken::Percent, lex.next_token());
+    assert_eq!(AsmToken::DecInteger, lex.next_token());
+    assert_eq!(10, lex.numeric_value().unwrap());
+    assert_eq!(AsmToken::BinInteger, lex.next_token());
+    assert_eq!(2, lex.numeric_value().unwrap());
+}
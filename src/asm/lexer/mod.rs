@@ -1,7 +1,8 @@
+#[allow(clippy::module_inception)]
 mod lexer;
 mod tokens;
 
-pub use lexer::AsmLexer;
+pub use lexer::{AsmLexer, Dialect};
 pub use tokens::AsmToken;
 
 #[cfg(test)]
@@ -24,16 +24,67 @@ pub enum AsmToken {
     #[token("=")]
     AssignmentOperator,
 
-    #[regex(r"(\$|0x)[0-9A_Fa-f]+")]
+    #[token("+")]
+    Plus,
+
+    #[token("-")]
+    Minus,
+
+    #[token("*")]
+    Asterisk,
+
+    #[token("/")]
+    Slash,
+
+    #[token("%")]
+    Percent,
+
+    #[token("&")]
+    Ampersand,
+
+    #[token("|")]
+    Pipe,
+
+    #[token("^")]
+    Caret,
+
+    #[token("~")]
+    Tilde,
+
+    #[token("<<")]
+    ShiftLeft,
+
+    #[token(">>")]
+    ShiftRight,
+
+    #[token("<")]
+    LessThan,
+
+    #[token(">")]
+    GreaterThan,
+
+    // digits may be separated by underscores, e.g. $ff_00 or %0101_1010
+    #[regex(r"(\$|0x)[0-9A-Fa-f][_0-9A-Fa-f]*")]
     HexInteger,
 
-    #[regex(r"[1-9][0-9]*")]
+    #[regex(r"[0-9][_0-9]*")]
     DecInteger,
 
+    // `%` is only a binary prefix when directly followed by a digit, so the
+    // modulo operator needs a space before binary digits: `a % 10`
+    #[regex(r"(%|0b)[01][_01]*")]
+    BinInteger,
+
+    #[regex(r"0o[0-7][_0-7]*")]
+    OctInteger,
+
+    #[regex(r"'([^'\\\n]|\\[^\n])+'")]
+    CharLiteral,
+
     #[regex(r"[_a-zA-Z][_a-zA-Z0-9]*")]
     Identifier,
 
-    #[regex(r#""[^"]*""#)]
+    #[regex(r#""([^"\\\n]|\\.)*""#)]
     StringLiteral,
 
     #[token("section")]
@@ -42,26 +93,67 @@ pub enum AsmToken {
     #[token(".str")]
     StrKeyword,
 
+    #[token(".ascii")]
+    AsciiKeyword,
+
+    #[token(".pstr")]
+    PStrKeyword,
+
     #[token(".word")]
     WordKeyword,
 
     #[token(".byte")]
     ByteKeyword,
 
+    #[token(".include")]
+    IncludeKeyword,
+
+    #[token(".res")]
+    ResKeyword,
+
+    #[token(".global")]
+    #[token(".export")]
+    GlobalKeyword,
+
+    #[token(".extern")]
+    #[token(".import")]
+    ExternKeyword,
+
     #[token("\n")]
     Newline,
 
+    // block comments may span multiple lines, so instead of skipping
+    // them here, AsmLexer drops them and keeps the line count intact.
+    #[token("/*", block_comment)]
+    BlockComment,
+
     // Logos requires one token variant to handle errors,
     // it can be named anything you wish.
     #[error]
     // We can also use this variant to define whitespace,
     // or any other matches we wish to skip.
     #[regex(r"[ \t\f]+", logos::skip)]
+    #[regex(r"//[^\n]*", logos::skip)]
     Error,
 
     End,
 }
 
+fn block_comment(lex: &mut logos::Lexer<AsmToken>) -> bool {
+    // consume everything up to and including the closing */, an
+    // unterminated comment swallows the rest of the source.
+    match lex.remainder().find("*/") {
+        Some(end) => {
+            lex.bump(end + 2);
+            true
+        }
+        None => {
+            lex.bump(lex.remainder().len());
+            false
+        }
+    }
+}
+
 impl fmt::Display for AsmToken {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
@@ -1,8 +1,17 @@
+mod assembler;
 mod codegen;
-pub(crate) mod ldscript;
+mod image;
+pub mod ldscript;
 mod lexer;
-mod model;
+pub mod model;
 mod parser;
 
-pub use codegen::CodeGenerator;
-pub use parser::AsmParser;
+pub use assembler::{
+    assemble, assemble_program, assemble_with_options, compile, compile_with_warnings, link,
+    link_with_archives, AsmOptions, Program, Source,
+};
+pub use codegen::{Archive, CodeGenerator, Linker, ObjectFile, SymbolFormat};
+pub use image::{Image, Segment};
+pub use ldscript::{LdScript, LdSection, MemoryRegion};
+pub use lexer::Dialect;
+pub use parser::{AsmParser, SectionSink};
@@ -1,5 +1,5 @@
 use super::codegen::get_opcode;
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 use strum::EnumString;
 
 #[derive(Debug, PartialEq)]
@@ -7,7 +7,11 @@ pub enum AsmStmt {
     AsmInstruction(Instruction),
     Data(DataPlacement),
     Label(String),
-    ConstLabel(String, u16),
+    ConstLabel(String, Expr),
+    // symbol visible to other files (.global/.export)
+    Global(String),
+    // symbol defined by another file (.extern/.import)
+    Extern(String),
 }
 
 impl AsmStmt {
@@ -23,14 +27,46 @@ impl AsmStmt {
 
     #[cfg(test)]
     pub fn new_const_label(name: String, addr: u16) -> AsmStmt {
-        AsmStmt::ConstLabel(name, addr)
+        AsmStmt::ConstLabel(name, Expr::Number(addr as i64))
+    }
+}
+
+/// Where a statement comes from. `text` is the complete source line, which
+/// is shared by all statements on that line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceLoc {
+    pub file: Option<String>,
+    pub line: u32,
+    pub text: String,
+}
+
+impl fmt::Display for SourceLoc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}", file, self.line),
+            None => write!(f, "line {}", self.line),
+        }
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct SourceStmt {
+    pub stmt: AsmStmt,
+    pub loc: SourceLoc,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum DataPlacement {
-    Str(String),
-    Word(MemRef),
+    // zero terminated string (.str)
+    Str(Vec<u8>),
+    // string without terminator (.ascii)
+    Ascii(Vec<u8>),
+    // string prefixed with its length byte (.pstr)
+    PStr(Vec<u8>),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+    // number of bytes to reserve without initializing them (.res)
+    Reserve(Expr),
 }
 
 #[derive(Debug, PartialEq)]
@@ -39,6 +75,7 @@ pub struct Instruction {
     addr_mode: AddrMode,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(EnumString, Debug, PartialEq, Copy, Clone)]
 pub enum Mnemonic {
     BRK,
@@ -143,6 +180,18 @@ pub enum Mnemonic {
     Invalid,
 }
 
+impl Mnemonic {
+    #[rustfmt::skip]
+    pub fn is_bit_branch(&self) -> bool {
+        use Mnemonic::*;
+        matches!(
+            self,
+            BBR0 | BBR1 | BBR2 | BBR3 | BBR4 | BBR5 | BBR6 | BBR7 |
+            BBS0 | BBS1 | BBS2 | BBS3 | BBS4 | BBS5 | BBS6 | BBS7
+        )
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum IndexMode {
     IndexedX,
@@ -153,27 +202,210 @@ pub enum IndexMode {
 #[derive(Debug, PartialEq, Clone)]
 pub enum AddrMode {
     Implied,
-    Immediate(u8),
-    Memory(IndexMode, MemRef),
+    Immediate(Expr),
+    Memory(IndexMode, Expr),
+    // indirect addressing: IndexedX stands for the pre-indexed form
+    // (ref,x), IndexedY for the post-indexed form (ref),y
+    Indirect(IndexMode, Expr),
+    // zeropage operand and branch target of BBRn/BBSn
+    BitBranch(Expr, Expr),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    LowByte,
+    HighByte,
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum MemRef {
-    Variable(String),
-    Addr(u16),
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EvalError {
+    UndefinedSymbol(String),
+    DivisionByZero,
+    InvalidShift(i64),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::UndefinedSymbol(name) => write!(f, "undefined reference to symbol {}", name),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::InvalidShift(amount) => write!(f, "invalid shift amount {}", amount),
+        }
+    }
+}
+
+impl Expr {
+    #[cfg(test)]
+    pub fn symbol(name: &str) -> Expr {
+        Expr::Symbol(name.into())
+    }
+
+    pub fn unary(op: UnaryOp, operand: Expr) -> Expr {
+        Expr::Unary(op, Box::new(operand))
+    }
+
+    pub fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn eval<F>(&self, lookup: &F) -> Result<i64, EvalError>
+    where
+        F: Fn(&str) -> Option<u16>,
+    {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Symbol(name) => match lookup(name) {
+                Some(addr) => Ok(addr as i64),
+                None => Err(EvalError::UndefinedSymbol(name.clone())),
+            },
+            Expr::Unary(op, operand) => {
+                let value = operand.eval(lookup)?;
+                Ok(match op {
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Not => !value,
+                    UnaryOp::LowByte => value & 0xff,
+                    UnaryOp::HighByte => (value >> 8) & 0xff,
+                })
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(lookup)?;
+                let rhs = rhs.eval(lookup)?;
+                match op {
+                    BinaryOp::Add => Ok(lhs.wrapping_add(rhs)),
+                    BinaryOp::Sub => Ok(lhs.wrapping_sub(rhs)),
+                    BinaryOp::Mul => Ok(lhs.wrapping_mul(rhs)),
+                    BinaryOp::Div => lhs.checked_div(rhs).ok_or(EvalError::DivisionByZero),
+                    BinaryOp::Mod => lhs.checked_rem(rhs).ok_or(EvalError::DivisionByZero),
+                    BinaryOp::And => Ok(lhs & rhs),
+                    BinaryOp::Or => Ok(lhs | rhs),
+                    BinaryOp::Xor => Ok(lhs ^ rhs),
+                    BinaryOp::Shl | BinaryOp::Shr => {
+                        if !(0..64).contains(&rhs) {
+                            return Err(EvalError::InvalidShift(rhs));
+                        }
+                        if *op == BinaryOp::Shl {
+                            Ok(lhs << rhs)
+                        } else {
+                            Ok(lhs >> rhs)
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Names of all symbols the expression refers to, in order of appearance.
+    pub fn symbols(&self) -> Vec<&str> {
+        match self {
+            Expr::Number(_) => vec![],
+            Expr::Symbol(name) => vec![name],
+            Expr::Unary(_, operand) => operand.symbols(),
+            Expr::Binary(_, lhs, rhs) => {
+                let mut symbols = lhs.symbols();
+                symbols.append(&mut rhs.symbols());
+                symbols
+            }
+        }
+    }
+
+    pub fn constant_value(&self) -> Option<i64> {
+        // value of expressions that don't reference any symbols
+        self.eval(&|_| None).ok()
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Number(value) => write!(f, "{}", value),
+            Expr::Symbol(name) => write!(f, "{}", name),
+            Expr::Unary(op, operand) => {
+                let op = match op {
+                    UnaryOp::Neg => "-",
+                    UnaryOp::Not => "~",
+                    UnaryOp::LowByte => "<",
+                    UnaryOp::HighByte => ">",
+                };
+                // nested operators are parenthesized, as `<<x` would be
+                // read back as a shift
+                match **operand {
+                    Expr::Unary(..) => write!(f, "{}({})", op, operand),
+                    _ => {
+                        write!(f, "{}", op)?;
+                        fmt_operand(f, operand)
+                    }
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let op = match op {
+                    BinaryOp::Add => "+",
+                    BinaryOp::Sub => "-",
+                    BinaryOp::Mul => "*",
+                    BinaryOp::Div => "/",
+                    BinaryOp::Mod => "%",
+                    BinaryOp::And => "&",
+                    BinaryOp::Or => "|",
+                    BinaryOp::Xor => "^",
+                    BinaryOp::Shl => "<<",
+                    BinaryOp::Shr => ">>",
+                };
+                // spaces keep e.g. `x % 10` from being read back as
+                // `x` followed by the binary number `%10`
+                fmt_operand(f, lhs)?;
+                write!(f, " {} ", op)?;
+                fmt_operand(f, rhs)
+            }
+        }
+    }
+}
+
+fn fmt_operand(f: &mut fmt::Formatter, operand: &Expr) -> fmt::Result {
+    if let Expr::Binary(..) = operand {
+        write!(f, "({})", operand)
+    } else {
+        write!(f, "{}", operand)
+    }
 }
 
 impl Instruction {
     pub fn new(mnemonic: String, addr_mode: AddrMode) -> Instruction {
         Instruction {
-            mnemonic: match Mnemonic::from_str(&mnemonic.to_uppercase()) {
-                Ok(m) => m,
-                Err(_) => Mnemonic::Invalid,
-            },
+            mnemonic: Instruction::parse_mnemonic(&mnemonic),
             addr_mode,
         }
     }
 
+    pub fn parse_mnemonic(mnemonic: &str) -> Mnemonic {
+        match Mnemonic::from_str(&mnemonic.to_uppercase()) {
+            Ok(m) => m,
+            Err(_) => Mnemonic::Invalid,
+        }
+    }
+
     pub fn addr_mode(&self) -> AddrMode {
         self.addr_mode.clone()
     }
@@ -0,0 +1,103 @@
+use super::{super::model::*, AsmParseError, AsmParser, AsmToken};
+use std::ops::RangeInclusive;
+
+impl<'a> AsmParser<'a> {
+    pub fn parse_str(&mut self, kind: AsmToken) {
+        self.lexer.next_token();
+        let bytes = self.parse_until(vec![AsmToken::Newline, AsmToken::Semicolon], |p| {
+            p.parse_string_literal()
+        });
+        let bytes = match bytes {
+            Some(bytes) => bytes,
+            None => return,
+        };
+
+        let data = match kind {
+            AsmToken::AsciiKeyword => DataPlacement::Ascii(bytes),
+            AsmToken::PStrKeyword if bytes.len() > 0xff => {
+                self.error(AsmParseError::StringTooLong(bytes.len()));
+                return;
+            }
+            AsmToken::PStrKeyword => DataPlacement::PStr(bytes),
+            _ => DataPlacement::Str(bytes),
+        };
+        self.push_stmt(AsmStmt::Data(data));
+    }
+
+    pub fn parse_string_literal(&mut self) -> Option<Vec<u8>> {
+        let token = self.lexer.current_token();
+        let value = match self.lexer.string_value() {
+            Some(value) => value,
+            None => {
+                self.error(AsmParseError::UnexpectedToken(token));
+                return None;
+            }
+        };
+
+        self.lexer.next_token();
+        match value {
+            Ok(bytes) => Some(bytes),
+            Err(escape) => {
+                self.error(AsmParseError::InvalidEscape(escape));
+                None
+            }
+        }
+    }
+
+    pub fn parse_bytes(&mut self) {
+        self.lexer.next_token();
+        let bytes = self.parse_until(vec![AsmToken::Newline, AsmToken::Semicolon], |p| {
+            p.parse_data_list(8, -0x80..=0xff)
+        });
+        if let Some(bytes) = bytes {
+            self.push_stmt(AsmStmt::Data(DataPlacement::Bytes(bytes)));
+        }
+    }
+
+    pub fn parse_words(&mut self) {
+        self.lexer.next_token();
+        let words = self.parse_until(vec![AsmToken::Newline, AsmToken::Semicolon], |p| {
+            p.parse_data_list(16, -0x8000..=0xffff)
+        });
+        if let Some(words) = words {
+            self.push_stmt(AsmStmt::Data(DataPlacement::Words(words)));
+        }
+    }
+
+    pub fn parse_reserve(&mut self) {
+        self.lexer.next_token();
+        let size = self.parse_until(vec![AsmToken::Newline, AsmToken::Semicolon], |p| {
+            let size = p.parse_expr()?;
+            match size.constant_value() {
+                Some(value) if !(0..=0xffff).contains(&value) => {
+                    p.error(AsmParseError::ReserveTooLarge);
+                    None
+                }
+                _ => Some(size),
+            }
+        });
+        if let Some(size) = size {
+            self.push_stmt(AsmStmt::Data(DataPlacement::Reserve(size)));
+        }
+    }
+
+    fn parse_data_list(&mut self, bits: u8, range: RangeInclusive<i64>) -> Option<Vec<Expr>> {
+        // comma separated list of expressions, e.g. `.byte 1, 2, $ff`
+        let mut values = vec![];
+        loop {
+            let value = self.parse_expr()?;
+            match value.constant_value() {
+                Some(constant) if !range.contains(&constant) => {
+                    self.error(AsmParseError::DataTooLarge(bits));
+                    return None;
+                }
+                _ => values.push(value),
+            }
+
+            if self.lexer.current_token() != AsmToken::Comma {
+                return Some(values);
+            }
+            self.lexer.next_token();
+        }
+    }
+}
@@ -5,8 +5,20 @@ pub enum AsmParseError {
     UnexpectedToken(AsmToken),
     ImmediateTooLarge,
     AddressTooLarge,
+    DataTooLarge(u8),
     InvalidIndexRegister(String),
+    InvalidIndirectIndex(String),
     ExcessTokens(usize),
+    UnknownFunction(String),
+    InvalidEscape(String),
+    StringTooLong(usize),
+    InvalidLiteral(String),
+    IncludeNotFound(String),
+    IncludeReadError(String, String),
+    IncludeTooDeep(usize),
+    ReserveTooLarge,
+    Redefinition(String, String),
+    ShadowsRegister(String),
 }
 
 impl ErrorMessage for AsmParseError {
@@ -21,10 +33,37 @@ impl ErrorMessage for AsmParseError {
             }
             AsmParseError::ImmediateTooLarge => "immediate value does not fit into 8 bits".into(),
             AsmParseError::AddressTooLarge => "address does not fit into 8 or 16 bits".into(),
+            AsmParseError::DataTooLarge(bits) => {
+                format!("data value does not fit into {} bits", bits)
+            }
             AsmParseError::InvalidIndexRegister(s) => {
                 format!("unknown index register '{}', use X or Y", s)
             }
+            AsmParseError::InvalidIndirectIndex(s) => {
+                format!(
+                    "cannot index with {} here, use (ref,X) or (ref),Y",
+                    s.to_uppercase()
+                )
+            }
             AsmParseError::ExcessTokens(c) => format!("{} excess tokens after construct", c),
+            AsmParseError::UnknownFunction(s) => format!("unknown function '{}', use lo or hi", s),
+            AsmParseError::InvalidEscape(s) => format!("invalid escape sequence '{}'", s),
+            AsmParseError::StringTooLong(len) => {
+                format!("string of {} bytes is too long for a length prefix", len)
+            }
+            AsmParseError::InvalidLiteral(s) => format!("invalid literal {}", s),
+            AsmParseError::IncludeNotFound(s) => format!("cannot find include file '{}'", s),
+            AsmParseError::IncludeReadError(s, e) => format!("cannot read '{}': {}", s, e),
+            AsmParseError::IncludeTooDeep(depth) => {
+                format!("includes nested more than {} levels deep", depth)
+            }
+            AsmParseError::ReserveTooLarge => "reserved size must be between 0 and 65535".into(),
+            AsmParseError::Redefinition(s, location) => {
+                format!("symbol {} is already defined at {}", s, location)
+            }
+            AsmParseError::ShadowsRegister(s) => {
+                format!("symbol {} shadows the pseudo register", s)
+            }
         }
     }
 }
@@ -0,0 +1,143 @@
+use super::{super::model::*, AsmParseError, AsmParser, AsmToken};
+
+// binary operators from lowest to highest precedence
+const PRECEDENCE_LEVELS: [&[(AsmToken, BinaryOp)]; 6] = [
+    &[(AsmToken::Pipe, BinaryOp::Or)],
+    &[(AsmToken::Caret, BinaryOp::Xor)],
+    &[(AsmToken::Ampersand, BinaryOp::And)],
+    &[
+        (AsmToken::ShiftLeft, BinaryOp::Shl),
+        (AsmToken::ShiftRight, BinaryOp::Shr),
+    ],
+    &[
+        (AsmToken::Plus, BinaryOp::Add),
+        (AsmToken::Minus, BinaryOp::Sub),
+    ],
+    &[
+        (AsmToken::Asterisk, BinaryOp::Mul),
+        (AsmToken::Slash, BinaryOp::Div),
+        (AsmToken::Percent, BinaryOp::Mod),
+    ],
+];
+
+impl<'a> AsmParser<'a> {
+    /// Parses text that consists of nothing but an expression, such as the
+    /// output of `Expr`'s Display implementation.
+    pub fn parse_expr_text(text: &str) -> Option<Expr> {
+        let mut parser = AsmParser::new(text);
+        parser.lexer.next_token();
+        let expr = parser.parse_expr()?;
+        if parser.lexer.current_token() != AsmToken::End || !parser.errors.is_empty() {
+            return None;
+        }
+        Some(expr)
+    }
+
+    /// Parses an expression starting at the current token. When done, the
+    /// current token is the first token after the expression.
+    pub fn parse_expr(&mut self) -> Option<Expr> {
+        self.parse_binary_expr(0)
+    }
+
+    fn parse_binary_expr(&mut self, level: usize) -> Option<Expr> {
+        if level == PRECEDENCE_LEVELS.len() {
+            return self.parse_unary_expr();
+        }
+
+        let mut lhs = self.parse_binary_expr(level + 1)?;
+        loop {
+            let token = self.lexer.current_token();
+            let op = match PRECEDENCE_LEVELS[level].iter().find(|(t, _)| *t == token) {
+                Some((_, op)) => *op,
+                None => return Some(lhs),
+            };
+
+            self.lexer.next_token();
+            let rhs = self.parse_binary_expr(level + 1)?;
+            lhs = Expr::binary(op, lhs, rhs);
+        }
+    }
+
+    fn parse_unary_expr(&mut self) -> Option<Expr> {
+        match self.lexer.current_token() {
+            AsmToken::Minus => {
+                self.lexer.next_token();
+                Some(Expr::unary(UnaryOp::Neg, self.parse_unary_expr()?))
+            }
+            AsmToken::Tilde => {
+                self.lexer.next_token();
+                Some(Expr::unary(UnaryOp::Not, self.parse_unary_expr()?))
+            }
+            AsmToken::LessThan => {
+                self.lexer.next_token();
+                Some(Expr::unary(UnaryOp::LowByte, self.parse_unary_expr()?))
+            }
+            AsmToken::GreaterThan => {
+                self.lexer.next_token();
+                Some(Expr::unary(UnaryOp::HighByte, self.parse_unary_expr()?))
+            }
+            AsmToken::Plus => {
+                self.lexer.next_token();
+                self.parse_unary_expr()
+            }
+            _ => self.parse_primary_expr(),
+        }
+    }
+
+    fn parse_primary_expr(&mut self) -> Option<Expr> {
+        let token = self.lexer.current_token();
+        let expr = match token {
+            AsmToken::DecInteger
+            | AsmToken::HexInteger
+            | AsmToken::BinInteger
+            | AsmToken::OctInteger
+            | AsmToken::CharLiteral => match self.lexer.numeric_value() {
+                Some(value) if value <= i64::MAX as u64 => Expr::Number(value as i64),
+                _ => {
+                    self.error(AsmParseError::InvalidLiteral(self.lexer.slice().into()));
+                    return None;
+                }
+            },
+            AsmToken::Identifier => {
+                let name: String = self.lexer.slice().into();
+                if self.lexer.next_token() != AsmToken::ParensOpen {
+                    return Some(Expr::Symbol(name));
+                }
+                self.parse_function(name)?
+            }
+            AsmToken::ParensOpen => self.parse_parenthesized_expr()?,
+            _ => {
+                self.error(AsmParseError::UnexpectedToken(token));
+                return None;
+            }
+        };
+
+        self.lexer.next_token();
+        Some(expr)
+    }
+
+    fn parse_function(&mut self, name: String) -> Option<Expr> {
+        // lo(expr) and hi(expr) are the same as <expr and >expr
+        let op = match name.to_lowercase().as_ref() {
+            "lo" => UnaryOp::LowByte,
+            "hi" => UnaryOp::HighByte,
+            _ => {
+                self.error(AsmParseError::UnknownFunction(name));
+                return None;
+            }
+        };
+        Some(Expr::unary(op, self.parse_parenthesized_expr()?))
+    }
+
+    fn parse_parenthesized_expr(&mut self) -> Option<Expr> {
+        // the closing parenthesis is left as the current token
+        self.lexer.next_token();
+        let expr = self.parse_expr()?;
+        let token = self.lexer.current_token();
+        if token != AsmToken::ParensClose {
+            self.error(AsmParseError::UnexpectedToken(token));
+            return None;
+        }
+        Some(expr)
+    }
+}
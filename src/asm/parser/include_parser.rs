@@ -0,0 +1,69 @@
+use super::{AsmParseError, AsmParser, AsmToken, SectionSink};
+use std::{fs, mem, path::PathBuf};
+
+// guards against files that (indirectly) include themselves
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+impl<'a> AsmParser<'a> {
+    pub fn parse_include<T: SectionSink>(&mut self, sink: &mut T) {
+        self.lexer.next_token();
+        let name = self.parse_until(vec![AsmToken::Newline, AsmToken::Semicolon], |p| {
+            p.parse_string_literal()
+        });
+        let name = match name {
+            Some(name) => String::from_utf8_lossy(&name).into_owned(),
+            None => return,
+        };
+
+        if self.include_depth == MAX_INCLUDE_DEPTH {
+            self.error(AsmParseError::IncludeTooDeep(MAX_INCLUDE_DEPTH));
+            return;
+        }
+        let path = match self.find_include(&name) {
+            Some(path) => path,
+            None => {
+                self.error(AsmParseError::IncludeNotFound(name));
+                return;
+            }
+        };
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(error) => {
+                self.error(AsmParseError::IncludeReadError(name, error.to_string()));
+                return;
+            }
+        };
+
+        // the included file continues in the current section and may switch
+        // sections itself, just as if its text was pasted in here.
+        let mut parser = AsmParser::with_dialect(&source, self.lexer.dialect());
+        parser.set_file_name(&path.to_string_lossy());
+        parser.include_paths = mem::take(&mut self.include_paths);
+        parser.include_depth = self.include_depth + 1;
+        parser.current_section_name = mem::take(&mut self.current_section_name);
+        parser.statements = mem::take(&mut self.statements);
+        parser.definitions = mem::take(&mut self.definitions);
+        parser.parse_statements(sink);
+
+        self.include_paths = parser.include_paths;
+        self.current_section_name = parser.current_section_name;
+        self.statements = parser.statements;
+        self.definitions = parser.definitions;
+        self.errors.append(&mut parser.errors);
+        self.warnings.append(&mut parser.warnings);
+    }
+
+    fn find_include(&self, name: &str) -> Option<PathBuf> {
+        // files are looked up relative to the including file first, then
+        // in the include paths in the order they were given.
+        let including_dir = self
+            .file_name
+            .as_ref()
+            .and_then(|file| PathBuf::from(file).parent().map(|dir| dir.to_path_buf()))
+            .unwrap_or_default();
+        std::iter::once(&including_dir)
+            .chain(self.include_paths.iter())
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+}
@@ -3,11 +3,16 @@ use crate::asm::model::Instruction;
 
 impl<'a> AsmParser<'a> {
     pub fn parse_instruction(&mut self, mnemonic: String) {
-        if let Some(addr_mode) = self.parse_addr_mode() {
-            self.statements
-                .push(AsmStmt::AsmInstruction(Instruction::new(
-                    mnemonic, addr_mode,
-                )));
+        let addr_mode = if Instruction::parse_mnemonic(&mnemonic).is_bit_branch() {
+            self.parse_bit_branch()
+        } else {
+            self.parse_addr_mode()
+        };
+
+        if let Some(addr_mode) = addr_mode {
+            self.push_stmt(AsmStmt::AsmInstruction(Instruction::new(
+                mnemonic, addr_mode,
+            )));
         }
     }
 
@@ -21,69 +26,120 @@ impl<'a> AsmParser<'a> {
         })
     }
 
+    fn parse_bit_branch(&mut self) -> Option<AddrMode> {
+        // BBRn/BBSn take a zeropage operand and a branch target: bbr0 zp, label
+        self.parse_until(vec![AsmToken::Newline, AsmToken::Semicolon], |p| {
+            let zp_ref = p.parse_address()?;
+            p.expect_token(AsmToken::Comma)?;
+            let target = p.parse_address()?;
+            Some(AddrMode::BitBranch(zp_ref, target))
+        })
+    }
+
     fn parse_immediate(&mut self) -> Option<AddrMode> {
         self.lexer.next_token();
-        let value = self.lexer.numeric_value()?;
-        if value >= 256 {
-            self.error(AsmParseError::ImmediateTooLarge);
-            None
-        } else {
-            Some(AddrMode::Immediate(value as u8))
+        let value = self.parse_expr()?;
+        match value.constant_value() {
+            Some(value) if !(-0x80..=0xff).contains(&value) => {
+                self.error(AsmParseError::ImmediateTooLarge);
+                None
+            }
+            _ => Some(AddrMode::Immediate(value)),
         }
     }
 
     fn parse_mem_addr_mode(&mut self) -> Option<AddrMode> {
-        self.parse_indexed_mem_ref()
+        if self.lexer.current_token() == AsmToken::ParensOpen {
+            self.parse_indirect_mem_ref()
+        } else {
+            self.parse_indexed_mem_ref()
+        }
     }
 
     fn parse_indexed_mem_ref(&mut self) -> Option<AddrMode> {
-        let mem_ref = self.parse_mem_ref()?;
-        if self.lexer.next_token() == AsmToken::Comma {
-            let id_token = self.lexer.next_token();
-            if let AsmToken::Identifier = id_token {
-                self.parse_index_mode(mem_ref)
-            } else {
-                self.error(AsmParseError::UnexpectedToken(id_token));
-                None
-            }
+        let mem_ref = self.parse_address()?;
+        if self.lexer.current_token() == AsmToken::Comma {
+            self.lexer.next_token();
+            let index_mode = self.parse_index_mode()?;
+            Some(AddrMode::Memory(index_mode, mem_ref))
         } else {
             Some(AddrMode::Memory(IndexMode::None, mem_ref))
         }
     }
 
-    pub fn parse_mem_ref(&mut self) -> Option<MemRef> {
-        let token = self.lexer.current_token();
-        match token {
-            AsmToken::DecInteger | AsmToken::HexInteger => {
-                let addr = self.parse_integer_literal()?;
-                if addr < 0x10000 {
-                    Some(MemRef::Addr(addr as u16))
-                } else {
-                    self.error(AsmParseError::AddressTooLarge);
-                    None
+    fn parse_indirect_mem_ref(&mut self) -> Option<AddrMode> {
+        self.lexer.next_token(); // skip opening parenthesis
+        let mem_ref = self.parse_address()?;
+        match self.lexer.current_token() {
+            AsmToken::Comma => {
+                // pre-indexed indirect: (ref,x)
+                self.lexer.next_token();
+                let index_mode = self.parse_index_mode()?;
+                self.expect_token(AsmToken::ParensClose)?;
+                if index_mode != IndexMode::IndexedX {
+                    self.error(AsmParseError::InvalidIndirectIndex("y".into()));
+                    return None;
                 }
+                Some(AddrMode::Indirect(IndexMode::IndexedX, mem_ref))
             }
-            AsmToken::Identifier => Some(MemRef::Variable(String::from(self.lexer.slice()))),
-            _ => {
+            AsmToken::ParensClose => {
+                // either plain indirect (ref) or post-indexed indirect (ref),y
+                if self.lexer.next_token() != AsmToken::Comma {
+                    return Some(AddrMode::Indirect(IndexMode::None, mem_ref));
+                }
+                self.lexer.next_token();
+                if self.parse_index_mode()? != IndexMode::IndexedY {
+                    self.error(AsmParseError::InvalidIndirectIndex("x".into()));
+                    return None;
+                }
+                Some(AddrMode::Indirect(IndexMode::IndexedY, mem_ref))
+            }
+            token => {
                 self.error(AsmParseError::UnexpectedToken(token));
                 None
             }
         }
     }
 
-    fn parse_index_mode(&mut self, mem_ref: MemRef) -> Option<AddrMode> {
-        let id_text = self.lexer.slice().to_lowercase();
-        match id_text.as_ref() {
-            "x" => Some(AddrMode::Memory(IndexMode::IndexedX, mem_ref)),
-            "y" => Some(AddrMode::Memory(IndexMode::IndexedY, mem_ref)),
-            _ => {
-                self.error(AsmParseError::InvalidIndexRegister(id_text));
+    pub fn expect_token(&mut self, expected: AsmToken) -> Option<()> {
+        let token = self.lexer.current_token();
+        if token == expected {
+            self.lexer.next_token();
+            Some(())
+        } else {
+            self.error(AsmParseError::UnexpectedToken(token));
+            None
+        }
+    }
+
+    pub fn parse_address(&mut self) -> Option<Expr> {
+        let addr = self.parse_expr()?;
+        match addr.constant_value() {
+            Some(value) if !(0..=0xffff).contains(&value) => {
+                self.error(AsmParseError::AddressTooLarge);
                 None
             }
+            _ => Some(addr),
         }
     }
 
-    fn parse_integer_literal(&mut self) -> Option<u64> {
-        self.lexer.numeric_value()
+    fn parse_index_mode(&mut self) -> Option<IndexMode> {
+        let id_token = self.lexer.current_token();
+        if id_token != AsmToken::Identifier {
+            self.error(AsmParseError::UnexpectedToken(id_token));
+            return None;
+        }
+
+        let id_text = self.lexer.slice().to_lowercase();
+        let index_mode = match id_text.as_ref() {
+            "x" => IndexMode::IndexedX,
+            "y" => IndexMode::IndexedY,
+            _ => {
+                self.error(AsmParseError::InvalidIndexRegister(id_text));
+                return None;
+            }
+        };
+        self.lexer.next_token();
+        Some(index_mode)
     }
 }
@@ -1,65 +1,157 @@
+mod data_parser;
 mod errors;
+mod expr_parser;
+mod include_parser;
 mod instruction_parser;
 
 #[cfg(test)]
 mod tests;
 
 use super::{
-    lexer::{AsmLexer, AsmToken},
-    model::AsmStmt,
+    lexer::{AsmLexer, AsmToken, Dialect},
+    model::{AsmStmt, SourceLoc, SourceStmt},
+};
+use crate::{
+    asm::{codegen::is_register, model::Expr},
+    errors::{CompileError, Diagnostics},
 };
-use crate::{asm::model::DataPlacement, errors::CompileError};
 use errors::AsmParseError;
+use std::{collections::HashMap, path::PathBuf};
 
 pub struct AsmParser<'a> {
     lexer: AsmLexer<'a>,
     errors: Vec<CompileError<AsmParseError>>,
+    warnings: Vec<CompileError<AsmParseError>>,
+    source_lines: Vec<&'a str>,
     current_section_name: String,
-    statements: Vec<AsmStmt>,
+    statements: Vec<SourceStmt>,
+    // labels and constants defined so far, including the included files
+    definitions: HashMap<String, SourceLoc>,
+    file_name: Option<String>,
+    include_paths: Vec<PathBuf>,
+    include_depth: usize,
 }
 
 pub trait SectionSink {
-    fn push_section(&mut self, name: &str, stmts: Vec<AsmStmt>);
+    fn push_section(&mut self, name: &str, stmts: Vec<SourceStmt>);
 }
 
 impl<'a> AsmParser<'a> {
-    pub fn new(source: &str) -> AsmParser {
+    pub fn new(source: &str) -> AsmParser<'_> {
+        AsmParser::with_dialect(source, Dialect::default())
+    }
+
+    pub fn with_dialect(source: &str, dialect: Dialect) -> AsmParser<'_> {
         AsmParser {
-            lexer: AsmLexer::new(source),
+            lexer: AsmLexer::new(source, dialect),
             errors: vec![],
+            warnings: vec![],
+            source_lines: source.lines().collect(),
             current_section_name: "text".into(),
             statements: vec![],
+            definitions: HashMap::new(),
+            file_name: None,
+            include_paths: vec![],
+            include_depth: 0,
         }
     }
 
+    /// Name of the parsed file, used for error messages and to find files
+    /// included relative to it.
+    pub fn set_file_name(&mut self, name: &str) {
+        self.file_name = Some(name.into());
+    }
+
+    /// Directories searched for `.include` files that aren't found relative
+    /// to the including file.
+    pub fn set_include_paths(&mut self, paths: &[PathBuf]) {
+        self.include_paths = paths.to_vec();
+    }
+
     #[cfg(test)]
     pub fn errors(&self) -> &Vec<CompileError<AsmParseError>> {
         &self.errors
     }
 
-    pub fn dump_errors(&self) -> usize {
-        let mut error_count = 0;
+    #[cfg(test)]
+    pub fn warnings(&self) -> &Vec<CompileError<AsmParseError>> {
+        &self.warnings
+    }
+
+    /// Errors followed by warnings
+    pub fn diagnostics(&self, file: &str) -> Diagnostics {
+        let mut diagnostics = Diagnostics::new();
         for error in self.errors.iter() {
-            error.print();
-            error_count += 1;
+            diagnostics.push(error.to_diagnostic(file));
+        }
+        for warning in self.warnings.iter() {
+            diagnostics.push(warning.to_warning(file));
         }
-        error_count
+        diagnostics
     }
 
     fn error(&mut self, error_type: AsmParseError) {
-        self.errors
-            .push(CompileError::new(error_type, self.lexer.line()));
+        let error = self.located(error_type);
+        self.errors.push(error);
     }
 
-    fn insert_label(&mut self, name: String, addr: Option<u16>) {
+    fn warning(&mut self, error_type: AsmParseError) {
+        let warning = self.located(error_type);
+        self.warnings.push(warning);
+    }
+
+    fn located(&self, error_type: AsmParseError) -> CompileError<AsmParseError> {
+        let line = self.lexer.line();
+        match &self.file_name {
+            Some(file) => CompileError::in_file(error_type, line, file),
+            None => CompileError::new(error_type, line),
+        }
+    }
+
+    fn current_loc(&self) -> SourceLoc {
+        // statements are pushed before the newline ending them is consumed,
+        // so the current line is the one they were found on.
+        let line = self.lexer.line();
+        let text = self.source_lines.get(line as usize - 1).unwrap_or(&"");
+        SourceLoc {
+            file: self.file_name.clone(),
+            line,
+            text: text.to_string(),
+        }
+    }
+
+    fn push_stmt(&mut self, stmt: AsmStmt) {
+        let loc = self.current_loc();
+        self.statements.push(SourceStmt { stmt, loc });
+    }
+
+    fn insert_label(&mut self, name: String, addr: Option<Expr>) {
+        if let Some(previous) = self.definitions.get(&name) {
+            let previous = previous.to_string();
+            self.error(AsmParseError::Redefinition(name, previous));
+            return;
+        }
+        if is_register(&name) {
+            self.warning(AsmParseError::ShadowsRegister(name.clone()));
+        }
+        self.definitions.insert(name.clone(), self.current_loc());
+
         if let Some(addr) = addr {
-            self.statements.push(AsmStmt::ConstLabel(name, addr));
+            self.push_stmt(AsmStmt::ConstLabel(name, addr));
         } else {
-            self.statements.push(AsmStmt::Label(name));
+            self.push_stmt(AsmStmt::Label(name));
         }
     }
 
     pub fn parse<T: SectionSink>(&mut self, sink: &mut T) {
+        self.parse_statements(sink);
+        sink.push_section(
+            &self.current_section_name,
+            std::mem::take(&mut self.statements),
+        );
+    }
+
+    fn parse_statements<T: SectionSink>(&mut self, sink: &mut T) {
         loop {
             match self.lexer.next_token() {
                 AsmToken::Identifier => {
@@ -78,32 +170,23 @@ impl<'a> AsmParser<'a> {
                     if token == AsmToken::Identifier {
                         sink.push_section(
                             &self.current_section_name,
-                            std::mem::replace(&mut self.statements, vec![]),
+                            std::mem::take(&mut self.statements),
                         );
                         self.current_section_name = self.lexer.slice().into();
                     } else {
                         self.error(AsmParseError::UnexpectedToken(token))
                     }
                 }
-                AsmToken::StrKeyword => {
-                    let token = self.lexer.next_token();
-                    if token == AsmToken::StringLiteral {
-                        let str_value = self.lexer.slice();
-                        self.statements.push(AsmStmt::Data(DataPlacement::Str(
-                            (&str_value[1..str_value.len() - 1]).into(),
-                        )));
-                    } else {
-                        self.error(AsmParseError::UnexpectedToken(token))
-                    }
-                },
-                AsmToken::WordKeyword => {
-                    self.lexer.next_token();
-                    if let Some(mem_ref) = self.parse_mem_ref() {
-                        self.statements.push(AsmStmt::Data(DataPlacement::Word(
-                            mem_ref
-                        )));
-                    }
-                },
+                token @ (AsmToken::StrKeyword | AsmToken::AsciiKeyword | AsmToken::PStrKeyword) => {
+                    self.parse_str(token)
+                }
+                AsmToken::ByteKeyword => self.parse_bytes(),
+                AsmToken::WordKeyword => self.parse_words(),
+                AsmToken::ResKeyword => self.parse_reserve(),
+                AsmToken::IncludeKeyword => self.parse_include(sink),
+                token @ (AsmToken::GlobalKeyword | AsmToken::ExternKeyword) => {
+                    self.parse_visibility(token)
+                }
                 AsmToken::End => break,
                 AsmToken::Newline | AsmToken::Semicolon => {}
                 token => {
@@ -111,39 +194,71 @@ impl<'a> AsmParser<'a> {
                 }
             }
         }
-        sink.push_section(
-            &self.current_section_name,
-            std::mem::replace(&mut self.statements, vec![]),
-        );
     }
 
-    fn parse_until<T, F: Fn(&mut Self) -> T>(&mut self, end_tokens: Vec<AsmToken>, func: F) -> T {
+    fn parse_until<T, F>(&mut self, end_tokens: Vec<AsmToken>, func: F) -> Option<T>
+    where
+        F: Fn(&mut Self) -> Option<T>,
+    {
+        // the construct is parsed starting at the current token and is expected
+        // to stop at the first token that doesn't belong to it anymore.
         let result = func(self);
 
-        let until_condition = |t: &&AsmToken| !end_tokens.contains(t) && t != &&AsmToken::End;
-        let mut unexpected_tokens: Vec<AsmToken> = vec![];
-        while until_condition(&&self.lexer.current_token()) {
-            unexpected_tokens.push(self.lexer.next_token());
+        let until_condition = |t: &AsmToken| !end_tokens.contains(t) && t != &AsmToken::End;
+        let mut excess_tokens = 0;
+        while until_condition(&self.lexer.current_token()) {
+            excess_tokens += 1;
+            self.lexer.next_token();
         }
 
-        let excess_tokens = unexpected_tokens.iter().filter(until_condition).count();
-        if excess_tokens > 0 {
+        // excess tokens are only reported if the construct itself was valid,
+        // otherwise they are most likely a consequence of the first error.
+        if excess_tokens > 0 && result.is_some() {
             self.error(AsmParseError::ExcessTokens(excess_tokens));
         }
 
         result
     }
 
-    fn parse_const_addr(&mut self, name: String) {
-        let token = self.lexer.next_token(); // skip assignment operator
-        if let Some(addr) = self.lexer.numeric_value() {
-            if addr <= 0xffff {
-                self.insert_label(name, Some(addr as u16));
+    fn parse_visibility(&mut self, kind: AsmToken) {
+        self.lexer.next_token();
+        let names = self.parse_until(vec![AsmToken::Newline, AsmToken::Semicolon], |p| {
+            p.parse_identifier_list()
+        });
+        for name in names.into_iter().flatten() {
+            if kind == AsmToken::GlobalKeyword {
+                self.push_stmt(AsmStmt::Global(name));
             } else {
-                self.error(AsmParseError::AddressTooLarge);
+                self.push_stmt(AsmStmt::Extern(name));
             }
-        } else {
-            self.error(AsmParseError::UnexpectedToken(token));
+        }
+    }
+
+    fn parse_identifier_list(&mut self) -> Option<Vec<String>> {
+        // comma separated list of symbol names, e.g. `.global putc, getc`
+        let mut names = vec![];
+        loop {
+            let token = self.lexer.current_token();
+            if token != AsmToken::Identifier {
+                self.error(AsmParseError::UnexpectedToken(token));
+                return None;
+            }
+            names.push(self.lexer.slice().into());
+
+            if self.lexer.next_token() != AsmToken::Comma {
+                return Some(names);
+            }
+            self.lexer.next_token();
+        }
+    }
+
+    fn parse_const_addr(&mut self, name: String) {
+        self.lexer.next_token(); // skip assignment operator
+        let addr = self.parse_until(vec![AsmToken::Newline, AsmToken::Semicolon], |p| {
+            p.parse_address()
+        });
+        if let Some(addr) = addr {
+            self.insert_label(name, Some(addr));
         }
     }
 }
@@ -0,0 +1,133 @@
+use crate::asm::{
+    model::{AsmStmt, BinaryOp, DataPlacement, Expr, UnaryOp},
+    parser::tests::StmtCollector,
+    AsmParser,
+};
+
+#[test]
+fn parse_data_lists() {
+    let mut parser = AsmParser::new(
+        r#"
+        .byte 1, 2, $ff
+        .byte -1
+        .word a, b+1, $c000
+        .word reset
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 0);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            AsmStmt::Data(DataPlacement::Bytes(vec![
+                Expr::Number(1),
+                Expr::Number(2),
+                Expr::Number(0xff)
+            ])),
+            AsmStmt::Data(DataPlacement::Bytes(vec![Expr::unary(
+                UnaryOp::Neg,
+                Expr::Number(1)
+            )])),
+            AsmStmt::Data(DataPlacement::Words(vec![
+                Expr::symbol("a"),
+                Expr::binary(BinaryOp::Add, Expr::symbol("b"), Expr::Number(1)),
+                Expr::Number(0xc000)
+            ])),
+            AsmStmt::Data(DataPlacement::Words(vec![Expr::symbol("reset")])),
+        ]
+    );
+}
+
+#[test]
+fn parse_invalid_data_lists() {
+    let mut parser = AsmParser::new(
+        r#"
+        .byte 1, $100
+        .byte -129
+        .word $10000
+        .word 1,
+        .byte
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 5);
+    assert_eq!(*stmts.statements(), vec![]);
+}
+
+#[test]
+fn parse_strings() {
+    let mut parser = AsmParser::new(
+        r#"
+        .str "hi\n"
+        .ascii "a\"b"
+        .pstr "\x01\\"
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 0);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            AsmStmt::Data(DataPlacement::Str(b"hi\n".to_vec())),
+            AsmStmt::Data(DataPlacement::Ascii(b"a\"b".to_vec())),
+            AsmStmt::Data(DataPlacement::PStr(vec![0x01, b'\\'])),
+        ]
+    );
+}
+
+#[test]
+fn parse_invalid_strings() {
+    let long_string = "x".repeat(256);
+    let source = format!(
+        r#"
+        .str "\y"
+        .ascii 12
+        .str "a" "b"
+        .pstr "{}"
+    "#,
+        long_string
+    );
+    let mut parser = AsmParser::new(&source);
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 4);
+    assert_eq!(
+        *stmts.statements(),
+        vec![AsmStmt::Data(DataPlacement::Str(b"a".to_vec()))]
+    );
+}
+
+#[test]
+fn parse_reserve() {
+    let mut parser = AsmParser::new(
+        r#"
+        .res 16
+        .res BUFFER_SIZE * 2
+        .res $10000
+        .res 1, 2
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 2);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            AsmStmt::Data(DataPlacement::Reserve(Expr::Number(16))),
+            AsmStmt::Data(DataPlacement::Reserve(Expr::binary(
+                BinaryOp::Mul,
+                Expr::symbol("BUFFER_SIZE"),
+                Expr::Number(2)
+            ))),
+            AsmStmt::Data(DataPlacement::Reserve(Expr::Number(1))),
+        ]
+    );
+}
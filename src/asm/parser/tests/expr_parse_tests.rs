@@ -0,0 +1,209 @@
+use crate::asm::{
+    model::{AddrMode, AsmStmt, BinaryOp, DataPlacement, Expr, IndexMode, UnaryOp},
+    parser::tests::StmtCollector,
+    AsmParser,
+};
+
+#[test]
+fn parse_operand_expressions() {
+    let mut parser = AsmParser::new(
+        r#"
+        lda table+1,x
+        ldx #BUF_SIZE-1
+        .word handler+2
+        end = start + 16
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 0);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            AsmStmt::new_instr(
+                "lda".into(),
+                AddrMode::Memory(
+                    IndexMode::IndexedX,
+                    Expr::binary(BinaryOp::Add, Expr::symbol("table"), Expr::Number(1))
+                )
+            ),
+            AsmStmt::new_instr(
+                "ldx".into(),
+                AddrMode::Immediate(Expr::binary(
+                    BinaryOp::Sub,
+                    Expr::symbol("BUF_SIZE"),
+                    Expr::Number(1)
+                ))
+            ),
+            AsmStmt::Data(DataPlacement::Words(vec![Expr::binary(
+                BinaryOp::Add,
+                Expr::symbol("handler"),
+                Expr::Number(2)
+            )])),
+            AsmStmt::ConstLabel(
+                "end".into(),
+                Expr::binary(BinaryOp::Add, Expr::symbol("start"), Expr::Number(16))
+            ),
+        ]
+    );
+}
+
+#[test]
+fn parse_operator_precedence() {
+    let mut parser = AsmParser::new(
+        r#"
+        .word 1 + 2 * 3
+        .word (1 + 2) * 3
+        .word -a % 4
+        .word ~$ff & mask | 1 << 4 ^ 2
+        .word a - b - c
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    let word = |expr| AsmStmt::Data(DataPlacement::Words(vec![expr]));
+    assert_eq!(parser.errors().len(), 0);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            word(Expr::binary(
+                BinaryOp::Add,
+                Expr::Number(1),
+                Expr::binary(BinaryOp::Mul, Expr::Number(2), Expr::Number(3))
+            )),
+            word(Expr::binary(
+                BinaryOp::Mul,
+                Expr::binary(BinaryOp::Add, Expr::Number(1), Expr::Number(2)),
+                Expr::Number(3)
+            )),
+            word(Expr::binary(
+                BinaryOp::Mod,
+                Expr::unary(UnaryOp::Neg, Expr::symbol("a")),
+                Expr::Number(4)
+            )),
+            word(Expr::binary(
+                BinaryOp::Or,
+                Expr::binary(
+                    BinaryOp::And,
+                    Expr::unary(UnaryOp::Not, Expr::Number(0xff)),
+                    Expr::symbol("mask")
+                ),
+                Expr::binary(
+                    BinaryOp::Xor,
+                    Expr::binary(BinaryOp::Shl, Expr::Number(1), Expr::Number(4)),
+                    Expr::Number(2)
+                )
+            )),
+            word(Expr::binary(
+                BinaryOp::Sub,
+                Expr::binary(BinaryOp::Sub, Expr::symbol("a"), Expr::symbol("b")),
+                Expr::symbol("c")
+            )),
+        ]
+    );
+}
+
+#[test]
+fn parse_invalid_expressions() {
+    let mut parser = AsmParser::new(
+        r#"
+        lda #(1 + 2
+        .word 3 +
+        ldx #$100 - 1 + 1
+        lda table + 1 junk
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    // excess tokens are reported, but the instruction itself is valid
+    assert_eq!(parser.errors().len(), 4);
+    assert_eq!(
+        *stmts.statements(),
+        vec![AsmStmt::new_instr(
+            "lda".into(),
+            AddrMode::Memory(
+                IndexMode::None,
+                Expr::binary(BinaryOp::Add, Expr::symbol("table"), Expr::Number(1))
+            )
+        )]
+    );
+}
+
+#[test]
+fn parse_byte_operators() {
+    let mut parser = AsmParser::new(
+        r#"
+        lda #<msg
+        lda #>msg
+        lda #LO(msg + 1)
+        lda #hi(msg)
+        lda #>msg + 1
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    let lda = |expr| AsmStmt::new_instr("lda".into(), AddrMode::Immediate(expr));
+    let msg = || Expr::symbol("msg");
+    assert_eq!(parser.errors().len(), 0);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            lda(Expr::unary(UnaryOp::LowByte, msg())),
+            lda(Expr::unary(UnaryOp::HighByte, msg())),
+            lda(Expr::unary(
+                UnaryOp::LowByte,
+                Expr::binary(BinaryOp::Add, msg(), Expr::Number(1))
+            )),
+            lda(Expr::unary(UnaryOp::HighByte, msg())),
+            lda(Expr::binary(
+                BinaryOp::Add,
+                Expr::unary(UnaryOp::HighByte, msg()),
+                Expr::Number(1)
+            )),
+        ]
+    );
+}
+
+#[test]
+fn parse_unknown_function() {
+    let mut parser = AsmParser::new("lda #bank(msg)");
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 1);
+}
+
+#[test]
+fn parse_literal_operands() {
+    let mut parser = AsmParser::new(
+        r#"
+        lda #0
+        cmp #'A'
+        and #%1000_0001
+        .byte 0o17, 0b11, 'x' - 'a'
+        .word 'ab'
+        .word 99999999999999999999
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 2);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            AsmStmt::new_instr("lda".into(), AddrMode::Immediate(Expr::Number(0))),
+            AsmStmt::new_instr("cmp".into(), AddrMode::Immediate(Expr::Number(0x41))),
+            AsmStmt::new_instr("and".into(), AddrMode::Immediate(Expr::Number(0x81))),
+            AsmStmt::Data(DataPlacement::Bytes(vec![
+                Expr::Number(0o17),
+                Expr::Number(3),
+                Expr::binary(BinaryOp::Sub, Expr::Number(0x78), Expr::Number(0x61))
+            ])),
+        ]
+    );
+}
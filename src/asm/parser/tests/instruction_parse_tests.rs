@@ -6,7 +6,7 @@ use crate::{
 #[test]
 fn parse_implied_and_immediate() {
     let mut parser = AsmParser::new(
-        &r#"
+        r#"
         brk
         inc ; inx
         dec;
@@ -27,8 +27,8 @@ fn parse_implied_and_immediate() {
             AsmStmt::new_instr("inx".into(), AddrMode::Implied),
             AsmStmt::new_instr("dec".into(), AddrMode::Implied),
             AsmStmt::new_instr("rts".into(), AddrMode::Implied),
-            AsmStmt::new_instr("lda".into(), AddrMode::Immediate(32)),
-            AsmStmt::new_instr("cmp".into(), AddrMode::Immediate(0xf0)),
+            AsmStmt::new_instr("lda".into(), AddrMode::Immediate(Expr::Number(32))),
+            AsmStmt::new_instr("cmp".into(), AddrMode::Immediate(Expr::Number(0xf0))),
             AsmStmt::new_instr("rti".into(), AddrMode::Implied),
         ]
     );
@@ -37,7 +37,7 @@ fn parse_implied_and_immediate() {
 #[test]
 fn parse_direct_mem_refs() {
     let mut parser = AsmParser::new(
-        &r#"
+        r#"
         jsr my_function
         lda $32
         stz 0xff
@@ -54,23 +54,114 @@ fn parse_direct_mem_refs() {
         vec![
             AsmStmt::new_instr(
                 "jsr".into(),
-                AddrMode::Memory(IndexMode::None, MemRef::Variable("my_function".into()))
+                AddrMode::Memory(IndexMode::None, Expr::symbol("my_function"))
             ),
             AsmStmt::new_instr(
                 "lda".into(),
-                AddrMode::Memory(IndexMode::None, MemRef::Addr(0x32))
+                AddrMode::Memory(IndexMode::None, Expr::Number(0x32))
             ),
             AsmStmt::new_instr(
                 "stz".into(),
-                AddrMode::Memory(IndexMode::None, MemRef::Addr(0xff))
+                AddrMode::Memory(IndexMode::None, Expr::Number(0xff))
             ),
             AsmStmt::new_instr(
                 "ldx".into(),
-                AddrMode::Memory(IndexMode::None, MemRef::Addr(218))
+                AddrMode::Memory(IndexMode::None, Expr::Number(218))
             ),
             AsmStmt::new_instr(
                 "stx".into(),
-                AddrMode::Memory(IndexMode::None, MemRef::Addr(0x8000))
+                AddrMode::Memory(IndexMode::None, Expr::Number(0x8000))
+            ),
+        ]
+    );
+}
+
+#[test]
+fn parse_indirect_mem_refs() {
+    let mut parser = AsmParser::new(
+        r#"
+        lda (ptr),y
+        lda (ptr,x)
+        sta ($12)
+        jmp (vector)
+        jmp (table,X)
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 0);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            AsmStmt::new_instr(
+                "lda".into(),
+                AddrMode::Indirect(IndexMode::IndexedY, Expr::symbol("ptr"))
+            ),
+            AsmStmt::new_instr(
+                "lda".into(),
+                AddrMode::Indirect(IndexMode::IndexedX, Expr::symbol("ptr"))
+            ),
+            AsmStmt::new_instr(
+                "sta".into(),
+                AddrMode::Indirect(IndexMode::None, Expr::Number(0x12))
+            ),
+            AsmStmt::new_instr(
+                "jmp".into(),
+                AddrMode::Indirect(IndexMode::None, Expr::symbol("vector"))
+            ),
+            AsmStmt::new_instr(
+                "jmp".into(),
+                AddrMode::Indirect(IndexMode::IndexedX, Expr::symbol("table"))
+            ),
+        ]
+    );
+}
+
+#[test]
+fn parse_invalid_indirect_index() {
+    let mut parser = AsmParser::new(
+        r#"
+        lda (ptr),x
+        lda (ptr,y)
+        lda (ptr
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 3);
+    assert_eq!(*stmts.statements(), vec![]);
+}
+
+#[test]
+fn parse_bit_branches() {
+    let mut parser = AsmParser::new(
+        r#"
+        bbr0 $12, target
+        BBS7 flags,loop
+        rmb3 flags
+        bbr1 flags
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 1);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            AsmStmt::new_instr(
+                "bbr0".into(),
+                AddrMode::BitBranch(Expr::Number(0x12), Expr::symbol("target"))
+            ),
+            AsmStmt::new_instr(
+                "bbs7".into(),
+                AddrMode::BitBranch(Expr::symbol("flags"), Expr::symbol("loop"))
+            ),
+            AsmStmt::new_instr(
+                "rmb3".into(),
+                AddrMode::Memory(IndexMode::None, Expr::symbol("flags"))
             ),
         ]
     );
@@ -1,20 +1,24 @@
 use std::collections::HashMap;
 
 use super::SectionSink;
-use crate::asm::model::AsmStmt;
+use crate::asm::model::{AsmStmt, SourceLoc, SourceStmt};
 
+mod data_parse_tests;
+mod expr_parse_tests;
 mod instruction_parse_tests;
 mod parse_tests;
 mod section_parse_tests;
 
 struct StmtCollector {
     stmts: HashMap<String, Vec<AsmStmt>>,
+    locations: HashMap<String, Vec<SourceLoc>>,
 }
 
 impl StmtCollector {
     pub fn new() -> StmtCollector {
         StmtCollector {
             stmts: HashMap::new(),
+            locations: HashMap::new(),
         }
     }
 
@@ -25,15 +29,19 @@ impl StmtCollector {
     pub fn section_statements(&self, name: &str) -> &Vec<AsmStmt> {
         self.stmts.get(name).unwrap()
     }
+
+    pub fn locations(&self) -> &Vec<SourceLoc> {
+        self.locations.get("text").unwrap()
+    }
 }
 
 impl SectionSink for StmtCollector {
-    fn push_section(&mut self, name: &str, stmts: Vec<AsmStmt>) {
-        let mut stmts = stmts;
-        if let Some(section_stmts) = self.stmts.get_mut(name) {
-            section_stmts.append(&mut stmts);
-        } else {
-            self.stmts.insert(name.into(), stmts);
+    fn push_section(&mut self, name: &str, stmts: Vec<SourceStmt>) {
+        let section_stmts = self.stmts.entry(name.into()).or_default();
+        let section_locations = self.locations.entry(name.into()).or_default();
+        for SourceStmt { stmt, loc } in stmts {
+            section_stmts.push(stmt);
+            section_locations.push(loc);
         }
     }
 }
@@ -1,5 +1,6 @@
 use crate::asm::{
-    model::{AddrMode, AsmStmt, IndexMode, MemRef},
+    lexer::Dialect,
+    model::{AddrMode, AsmStmt, Expr, IndexMode},
     parser::tests::StmtCollector,
     AsmParser,
 };
@@ -7,7 +8,7 @@ use crate::asm::{
 #[test]
 fn simple_labels() {
     let mut parser = AsmParser::new(
-        &r#"
+        r#"
             brk
             driver_addr = $34;
         my_label:
@@ -29,8 +30,139 @@ fn simple_labels() {
             AsmStmt::new_label("tw".into()),
             AsmStmt::new_instr(
                 "lda".into(),
-                AddrMode::Memory(IndexMode::None, MemRef::Variable("variable".into()))
+                AddrMode::Memory(IndexMode::None, Expr::symbol("variable"))
             ),
         ]
     );
 }
+
+#[test]
+fn comments() {
+    let mut parser = AsmParser::with_dialect(
+        r#"
+        /* reset handler,
+           clears the flags */
+        reset:  ; entry point
+            brk // break
+            ; cli
+    "#,
+        Dialect::SemicolonComment,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 0);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            AsmStmt::new_label("reset".into()),
+            AsmStmt::new_instr("brk".into(), AddrMode::Implied),
+        ]
+    );
+}
+
+#[test]
+fn missing_include() {
+    let mut parser = AsmParser::new(
+        r#"
+        .include "does/not/exist.s"
+        .include nothing
+        rts
+    "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 2);
+    assert_eq!(
+        *stmts.statements(),
+        vec![AsmStmt::new_instr("rts".into(), AddrMode::Implied)]
+    );
+}
+
+#[test]
+fn statement_locations() {
+    let mut parser = AsmParser::new("start: lda #1\n/* two\nlines */ nop; rts\n\n.byte 1\n");
+    parser.set_file_name("main.s");
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 0);
+    let locations: Vec<(Option<&str>, u32, &str)> = stmts
+        .locations()
+        .iter()
+        .map(|loc| (loc.file.as_deref(), loc.line, loc.text.as_str()))
+        .collect();
+    assert_eq!(
+        locations,
+        vec![
+            (Some("main.s"), 1, "start: lda #1"),
+            (Some("main.s"), 1, "start: lda #1"),
+            (Some("main.s"), 3, "lines */ nop; rts"),
+            (Some("main.s"), 3, "lines */ nop; rts"),
+            (Some("main.s"), 5, ".byte 1"),
+        ]
+    );
+}
+
+#[test]
+fn visibility_directives() {
+    let mut parser = AsmParser::new(
+        r#"
+            .global putc, getc
+            .export puts
+            .extern ACIA
+            .import strlen, 1
+            .global
+        "#,
+    );
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 2);
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            AsmStmt::Global("putc".into()),
+            AsmStmt::Global("getc".into()),
+            AsmStmt::Global("puts".into()),
+            AsmStmt::Extern("ACIA".into()),
+        ]
+    );
+}
+
+#[test]
+fn redefined_labels() {
+    let mut parser = AsmParser::new("start: nop\nend = $20\nstart: rts\nend: brk\nr3: rts\n");
+    parser.set_file_name("main.s");
+    let mut stmts = StmtCollector::new();
+    parser.parse(&mut stmts);
+
+    assert_eq!(parser.errors().len(), 2);
+    assert_eq!(parser.warnings().len(), 1);
+    let messages: Vec<String> = parser
+        .diagnostics("main.s")
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            "main.s:3: error: symbol start is already defined at main.s:1",
+            "main.s:4: error: symbol end is already defined at main.s:2",
+            "main.s:5: warning: symbol r3 shadows the pseudo register",
+        ]
+    );
+    assert_eq!(
+        *stmts.statements(),
+        vec![
+            AsmStmt::Label("start".into()),
+            AsmStmt::new_instr("nop".into(), AddrMode::Implied),
+            AsmStmt::ConstLabel("end".into(), Expr::Number(0x20)),
+            AsmStmt::new_instr("rts".into(), AddrMode::Implied),
+            AsmStmt::new_instr("brk".into(), AddrMode::Implied),
+            AsmStmt::Label("r3".into()),
+            AsmStmt::new_instr("rts".into(), AddrMode::Implied),
+        ]
+    );
+}
@@ -7,7 +7,7 @@ use crate::asm::{
 #[test]
 fn simple_sections() {
     let mut parser = AsmParser::new(
-        &r#"
+        r#"
             brk
             clc
             section other_section
@@ -0,0 +1,155 @@
+use super::{parse_args, Mode, Options, OutputFormat};
+use retro_lang::{Dialect, SymbolFormat};
+use std::path::PathBuf;
+
+fn parse(args: &[&str]) -> Result<Options, String> {
+    parse_args(args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn parse_default_options() {
+    let options = parse(&["main.s", "data.s"]).unwrap();
+
+    assert_eq!(options.mode, Mode::Assemble);
+    assert_eq!(options.sources, vec!["main.s", "data.s"]);
+    assert_eq!(options.output(), "output.bin");
+    assert_eq!(options.ldscript, None);
+    assert_eq!(options.format, OutputFormat::Binary);
+    assert_eq!(options.entry, None);
+    assert_eq!(options.listing, None);
+    assert_eq!(options.map, None);
+    assert_eq!(options.symbols, None);
+    assert_eq!(options.symbol_format, SymbolFormat::Text);
+    assert!(options.include_paths.is_empty());
+    assert!(options.defines.is_empty());
+    assert_eq!(options.dialect, Dialect::SemicolonSeparator);
+}
+
+#[test]
+fn parse_all_options() {
+    let options = parse(&[
+        "-o",
+        "rom.bin",
+        "-Tboard.ld",
+        "-f",
+        "srec",
+        "-ereset",
+        "-l",
+        "rom.lst",
+        "-mrom.map",
+        "-s",
+        "rom.lbl",
+        "-Svice",
+        "-I",
+        "inc",
+        "-Ilib",
+        "-D",
+        "DEBUG",
+        "-DBAUD=$10",
+        "--semicolon-comments",
+        "main.s",
+    ])
+    .unwrap();
+
+    assert_eq!(options.sources, vec!["main.s"]);
+    assert_eq!(options.output(), "rom.bin");
+    assert_eq!(options.ldscript, Some("board.ld".into()));
+    assert_eq!(options.format, OutputFormat::SRecord);
+    assert_eq!(options.entry, Some("reset".into()));
+    assert_eq!(options.listing, Some("rom.lst".into()));
+    assert_eq!(options.map, Some("rom.map".into()));
+    assert_eq!(options.symbols, Some("rom.lbl".into()));
+    assert_eq!(options.symbol_format, SymbolFormat::Vice);
+    assert_eq!(
+        options.include_paths,
+        vec![PathBuf::from("inc"), PathBuf::from("lib")]
+    );
+    assert_eq!(
+        options.defines,
+        vec![("DEBUG".into(), "1".into()), ("BAUD".into(), "$10".into())]
+    );
+    assert_eq!(options.defines_source(), "DEBUG = 1\nBAUD = $10\n");
+    assert_eq!(options.dialect, Dialect::SemicolonComment);
+}
+
+#[test]
+fn parse_invalid_options() {
+    assert!(parse(&[]).is_err());
+    assert!(parse(&["-o"]).is_err());
+    assert!(parse(&["-x", "main.s"]).is_err());
+    assert_eq!(parse(&["-é", "main.s"]), Err("unknown option -é".into()));
+    assert!(parse(&["-f", "elf", "main.s"]).is_err());
+    assert!(parse(&["-S", "elf", "main.s"]).is_err());
+    assert!(parse(&["-D", "=1", "main.s"]).is_err());
+    assert_eq!(
+        parse(&["-DX=1;brk", "main.s"]),
+        Err("invalid value '1;brk' in -D X=1;brk, expected an expression".into())
+    );
+    assert!(parse(&["-D", "X=1\nbrk", "main.s"]).is_err());
+    assert!(parse(&["-D", "X=1 2", "main.s"]).is_err());
+    assert!(parse(&["-D", "brk;X=1", "main.s"]).is_err());
+    assert!(parse(&["-D", "1X", "main.s"]).is_err());
+    assert!(parse(&["-D", "X=(1 + 2) * 3", "main.s"]).is_ok());
+    assert!(parse(&["--help"]).unwrap().help);
+}
+
+#[test]
+fn parse_compile_and_link() {
+    let options = parse(&["-c", "src/main.s", "lib.s"]).unwrap();
+    assert_eq!(options.mode, Mode::Compile);
+    assert_eq!(options.object_output("src/main.s"), "src/main.o");
+    assert_eq!(options.object_output("lib.s"), "lib.o");
+
+    let options = parse(&["-c", "-o", "out.o", "main.s"]).unwrap();
+    assert_eq!(options.object_output("main.s"), "out.o");
+    assert!(parse(&["-c", "-o", "out.o", "main.s", "lib.s"]).is_err());
+
+    let options = parse(&["link", "-T", "rom.ld", "main.o", "lib.o"]).unwrap();
+    assert_eq!(options.mode, Mode::Link);
+    assert_eq!(options.sources, vec!["main.o", "lib.o"]);
+    assert_eq!(parse(&["link"]).unwrap_err(), "no object files given");
+}
+
+#[test]
+fn parse_archive() {
+    let options = parse(&["archive", "-o", "libio.a", "putc.o", "getc.o"]).unwrap();
+    assert_eq!(options.mode, Mode::Archive);
+    assert_eq!(options.output(), "libio.a");
+    assert_eq!(options.sources, vec!["putc.o", "getc.o"]);
+    assert_eq!(
+        parse(&["archive", "putc.o"]).unwrap_err(),
+        "no archive name given, use -o <archive>"
+    );
+    assert_eq!(
+        parse(&["archive", "-o", "libio.a"]).unwrap_err(),
+        "no object files given"
+    );
+}
+
+#[test]
+fn parse_options_of_other_modes() {
+    // options a mode has no use for are rejected instead of ignored
+    assert_eq!(
+        parse(&["-c", "-l", "main.lst", "main.s"]),
+        Err("option -l can't be used with -c".into())
+    );
+    for flag in ["-T", "-f", "-e", "-m", "-s", "-S"] {
+        assert!(parse(&["-c", flag, "x", "main.s"]).is_err(), "{}", flag);
+    }
+    assert!(parse(&["-c", "-I", "inc", "-DX", "main.s"]).is_ok());
+
+    assert_eq!(
+        parse(&["link", "-I", "inc", "main.o"]),
+        Err("option -I can't be used with link".into())
+    );
+    assert!(parse(&["link", "-T", "rom.ld", "-m", "rom.map", "main.o"]).is_ok());
+
+    assert_eq!(
+        parse(&["archive", "-o", "libio.a", "-fihex", "putc.o"]),
+        Err("option -f can't be used with archive".into())
+    );
+    assert_eq!(
+        parse(&["archive", "-o", "libio.a", "--semicolon-comments", "putc.o"]),
+        Err("option --semicolon-comments can't be used with archive".into())
+    );
+}
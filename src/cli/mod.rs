@@ -0,0 +1,254 @@
+use retro_lang::{AsmParser, Dialect, SymbolFormat};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[cfg(test)]
+mod cli_tests;
+
+pub const USAGE: &str = "\
+usage: retro-lang [options] <source>...
+       retro-lang -c [options] <source>...
+       retro-lang link [options] <object|archive>...
+       retro-lang archive -o <archive> <object>...
+
+options:
+  -c                  only assemble, write an object file for every source
+  -o <file>           write the output to <file> (default: output.bin, or
+                      the source name with .o for -c)
+  -T <file>           link according to the linker script in <file>
+  -f <format>         output format: bin (default), ihex, srec
+  -e <symbol>         record the address of <symbol> as entry point (srec)
+  -l <file>           write a listing with the generated code to <file>
+  -m <file>           write a map of sections, regions and symbols to <file>
+  -s <file>           write all symbols to <file>
+  -S <format>         symbol file format: txt (default), vice, json
+  -I <dir>            search <dir> for .include files
+  -D <name>[=<value>] define the constant <name> (default value: 1)
+  --semicolon-comments
+                      `;` starts a comment instead of separating statements
+  -h, --help          print this help";
+
+// options for linking and writing the linked program
+const LINK_OPTIONS: &[&str] = &["-T", "-f", "-e", "-l", "-m", "-s", "-S"];
+// options for reading sources
+const SOURCE_OPTIONS: &[&str] = &["-I", "-D", "--semicolon-comments"];
+
+#[derive(Debug, PartialEq)]
+pub enum OutputFormat {
+    Binary,
+    IntelHex,
+    SRecord,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bin" => Ok(OutputFormat::Binary),
+            "ihex" => Ok(OutputFormat::IntelHex),
+            "srec" => Ok(OutputFormat::SRecord),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+/// What to do with the input files
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    /// assemble and link sources in one go
+    Assemble,
+    /// assemble sources into object files
+    Compile,
+    /// link object files and archives
+    Link,
+    /// bundle object files into an archive
+    Archive,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub mode: Mode,
+    /// sources, or object files and archives
+    pub sources: Vec<String>,
+    pub output: Option<String>,
+    pub ldscript: Option<String>,
+    pub format: OutputFormat,
+    pub entry: Option<String>,
+    pub listing: Option<String>,
+    pub map: Option<String>,
+    pub symbols: Option<String>,
+    pub symbol_format: SymbolFormat,
+    pub include_paths: Vec<PathBuf>,
+    pub defines: Vec<(String, String)>,
+    pub dialect: Dialect,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            mode: Mode::Assemble,
+            sources: vec![],
+            output: None,
+            ldscript: None,
+            format: OutputFormat::Binary,
+            entry: None,
+            listing: None,
+            map: None,
+            symbols: None,
+            symbol_format: SymbolFormat::Text,
+            include_paths: vec![],
+            defines: vec![],
+            dialect: Dialect::default(),
+            help: false,
+        }
+    }
+}
+
+impl Options {
+    /// Output file of the linked image
+    pub fn output(&self) -> &str {
+        self.output.as_deref().unwrap_or("output.bin")
+    }
+
+    /// Object file written for a source with `-c`
+    pub fn object_output(&self, source: &str) -> String {
+        match &self.output {
+            Some(output) => output.clone(),
+            None => Path::new(source)
+                .with_extension("o")
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+    /// Source text assigning every `-D` definition, so that definitions go
+    /// through the same parser and error reporting as any other constant.
+    pub fn defines_source(&self) -> String {
+        self.defines
+            .iter()
+            .map(|(name, value)| format!("{} = {}\n", name, value))
+            .collect()
+    }
+}
+
+/// Parses the command line arguments, not including the program name.
+/// Option values may either be attached (`-Iinclude`) or separate
+/// (`-I include`).
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.peekable();
+    match args.peek().map(String::as_str) {
+        Some("link") => options.mode = Mode::Link,
+        Some("archive") => options.mode = Mode::Archive,
+        _ => {}
+    }
+    if options.mode != Mode::Assemble {
+        args.next();
+    }
+    // options given, to check that the mode makes use of them
+    let mut flags = vec![];
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            options.help = true;
+            continue;
+        }
+        if arg == "--semicolon-comments" {
+            options.dialect = Dialect::SemicolonComment;
+            flags.push(arg);
+            continue;
+        }
+        if arg == "-c" && options.mode == Mode::Assemble {
+            options.mode = Mode::Compile;
+            continue;
+        }
+        if !arg.starts_with('-') || arg.len() < 2 {
+            options.sources.push(arg);
+            continue;
+        }
+
+        // the flag may be followed by a character that isn't ASCII
+        let Some((flag, attached)) = arg.split_at_checked(2) else {
+            return Err(format!("unknown option {}", arg));
+        };
+        let mut value = || {
+            if !attached.is_empty() {
+                Ok(attached.to_string())
+            } else {
+                args.next()
+                    .ok_or_else(|| format!("option {} requires a value", flag))
+            }
+        };
+        match flag {
+            "-o" => options.output = Some(value()?),
+            "-T" => options.ldscript = Some(value()?),
+            "-f" => options.format = value()?.parse()?,
+            "-e" => options.entry = Some(value()?),
+            "-l" => options.listing = Some(value()?),
+            "-m" => options.map = Some(value()?),
+            "-s" => options.symbols = Some(value()?),
+            "-S" => options.symbol_format = value()?.parse()?,
+            "-I" => options.include_paths.push(value()?.into()),
+            "-D" => options.defines.push(parse_define(&value()?)?),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+        flags.push(flag.to_string());
+    }
+
+    // options that only affect another mode would be silently ignored
+    let (mode, unused): (&str, Vec<&str>) = match options.mode {
+        Mode::Assemble => ("", vec![]),
+        Mode::Compile => ("-c", LINK_OPTIONS.to_vec()),
+        Mode::Link => ("link", SOURCE_OPTIONS.to_vec()),
+        Mode::Archive => ("archive", [LINK_OPTIONS, SOURCE_OPTIONS].concat()),
+    };
+    if let Some(flag) = flags.iter().find(|flag| unused.contains(&flag.as_str())) {
+        return Err(format!("option {} can't be used with {}", flag, mode));
+    }
+
+    if options.sources.is_empty() && !options.help {
+        return Err(match options.mode {
+            Mode::Link | Mode::Archive => "no object files given".into(),
+            _ => "no source files given".into(),
+        });
+    }
+    if options.mode == Mode::Compile && options.output.is_some() && options.sources.len() > 1 {
+        return Err("-o can't be used with -c and more than one source".into());
+    }
+    if options.mode == Mode::Archive && options.output.is_none() && !options.help {
+        return Err("no archive name given, use -o <archive>".into());
+    }
+    Ok(options)
+}
+
+fn parse_define(define: &str) -> Result<(String, String), String> {
+    let (name, value) = match define.split_once('=') {
+        Some((name, value)) => (name, value),
+        None => (define, "1"),
+    };
+    if name.is_empty() || value.is_empty() {
+        return Err(format!(
+            "invalid definition '{}', use -D NAME=VALUE",
+            define
+        ));
+    }
+    // both end up in generated source, so anything but a symbol name and a
+    // single expression could add statements to it
+    let mut chars = name.chars();
+    let is_identifier = chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric());
+    if !is_identifier {
+        return Err(format!("invalid name '{}' in -D {}", name, define));
+    }
+    if AsmParser::parse_expr_text(value).is_none() {
+        return Err(format!(
+            "invalid value '{}' in -D {}, expected an expression",
+            value, define
+        ));
+    }
+    Ok((name.into(), value.into()))
+}
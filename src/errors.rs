@@ -1,18 +1,186 @@
+use std::fmt;
+
 pub trait ErrorMessage {
     fn error_msg(&self) -> String;
 }
 
+#[derive(Debug)]
 pub struct CompileError<T: ErrorMessage> {
     error_type: T,
     line: u32,
+    file: Option<String>,
 }
 
 impl<T: ErrorMessage> CompileError<T> {
-    pub fn print(&self) {
-        println!("parse error: line {}: {}", self.line, self.error_type.error_msg());
+    pub fn new(error_type: T, line: u32) -> CompileError<T> {
+        CompileError {
+            error_type,
+            line,
+            file: None,
+        }
     }
 
-    pub fn new(error_type: T, line: u32) -> CompileError<T> {
-        CompileError { error_type, line }
+    /// Error located in a file other than the one being compiled, i.e. one
+    /// that has been included.
+    pub fn in_file(error_type: T, line: u32, file: &str) -> CompileError<T> {
+        CompileError {
+            error_type,
+            line,
+            file: Some(file.into()),
+        }
+    }
+
+    pub fn to_diagnostic(&self, file: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            file: Some(self.file.as_deref().unwrap_or(file).into()),
+            line: Some(self.line),
+            message: self.error_type.error_msg(),
+        }
+    }
+
+    /// Same as `to_diagnostic`, for problems that don't stop the assembler.
+    pub fn to_warning(&self, file: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..self.to_diagnostic(file)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// An error or warning reported by the assembler, with the source location
+/// if known.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    severity: Severity,
+    file: Option<String>,
+    line: Option<u32>,
+    message: String,
+}
+
+impl Diagnostic {
+    pub fn new(message: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            file: None,
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn in_file(file: &str, message: &str) -> Diagnostic {
+        Diagnostic {
+            file: Some(file.into()),
+            ..Diagnostic::new(message)
+        }
+    }
+
+    /// Error found at a line of a source, which may not have a name.
+    pub fn at(file: Option<&str>, line: u32, message: &str) -> Diagnostic {
+        Diagnostic {
+            file: file.map(String::from),
+            line: Some(line),
+            ..Diagnostic::new(message)
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
     }
 }
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{}: ", file, line)?,
+            (Some(file), None) => write!(f, "{}: ", file)?,
+            (None, Some(line)) => write!(f, "line {}: ", line)?,
+            _ => {}
+        }
+        write!(f, "{}: {}", severity, self.message)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    /// Diagnostics for error messages without a source location, e.g. ones
+    /// about the linker script or the sections as a whole.
+    pub fn from_messages(messages: Vec<String>) -> Diagnostics {
+        Diagnostics {
+            diagnostics: messages.iter().map(|m| Diagnostic::new(m)).collect(),
+        }
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn append(&mut self, mut other: Diagnostics) {
+        self.diagnostics.append(&mut other.diagnostics);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+}
+
+impl From<Vec<Diagnostic>> for Diagnostics {
+    fn from(diagnostics: Vec<Diagnostic>) -> Diagnostics {
+        Diagnostics { diagnostics }
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for diagnostic in self.diagnostics.iter() {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
@@ -0,0 +1,19 @@
+//! Assembler and linker for the WDC 65C02.
+//!
+//! [`assemble`] covers the common case of turning a set of sources into a
+//! linked [`Image`]. Sources can also be assembled separately with
+//! [`compile`] into [`ObjectFile`]s, which [`link`] combines later on.
+//! Objects shared between programs can be bundled into an [`Archive`], from
+//! which [`link_with_archives`] only takes what a program needs. The parser
+//! and code generator can also be driven directly, see [`AsmParser`] and
+//! [`CodeGenerator`].
+
+pub mod asm;
+mod errors;
+
+pub use asm::{
+    assemble, assemble_program, assemble_with_options, compile, compile_with_warnings, link,
+    link_with_archives, Archive, AsmOptions, AsmParser, CodeGenerator, Dialect, Image, LdScript,
+    LdSection, Linker, ObjectFile, Program, Segment, Source, SymbolFormat,
+};
+pub use errors::{Diagnostic, Diagnostics, Severity};
@@ -1,43 +1,146 @@
-use std::{env, fs, io::Write};
-mod asm;
-mod errors;
-use asm::{AsmParser, CodeGenerator};
+use retro_lang::{
+    asm::ldscript, assemble_program, compile_with_warnings, link_with_archives, Archive,
+    AsmOptions, Diagnostics, LdScript, LdSection, ObjectFile, Source,
+};
+use std::{env, fs, path::Path, process};
 
-use crate::asm::ldscript::LdSection;
+mod cli;
+use cli::{Mode, OutputFormat};
 
 fn main() {
-    let mut codegen = CodeGenerator::new();
-    for filename in env::args().skip(1) {
-        let source = match fs::read_to_string(&filename) {
-            Ok(source) => source,
-            Err(_) => {
-                println!("error: {}: cannot open file", &filename);
-                return;
-            }
-        };
-
-        let mut parser = AsmParser::new(&source);
-        parser.parse(&mut codegen);
-
-        if parser.dump_errors() != 0 {
-            return;
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, cli::USAGE);
+            process::exit(2);
         }
+    };
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
     }
 
-    let ldscript = vec![
-        LdSection::new("text", Some(0xe000)),
-        LdSection::new("data", None),
-    ];
-
-    match codegen.link(ldscript) {
-        Ok(binary) => {
-            let mut file = fs::File::create("output.bin").unwrap();
-            file.write(&binary).unwrap();
-        },
-        Err(errors) => {
-            for error in errors {
-                println!("codegen error: {}", error);
+    if let Err(error) = run(&options) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn run(options: &cli::Options) -> Result<(), String> {
+    let asm_options = AsmOptions {
+        include_paths: options.include_paths.clone(),
+        entry: options.entry.clone(),
+        defines: (!options.defines.is_empty()).then(|| defines_source(options)),
+        dialect: options.dialect,
+    };
+
+    let program = match options.mode {
+        Mode::Assemble => {
+            let mut sources = vec![];
+            for filename in options.sources.iter() {
+                sources.push(Source::new(filename, &read_file(filename)?));
+            }
+            let program = assemble_program(&sources, load_ldscript(options)?, &asm_options)
+                .map_err(format_diagnostics)?;
+            eprint!("{}", program.warnings());
+            program
+        }
+        Mode::Compile => {
+            for filename in options.sources.iter() {
+                let source = Source::new(filename, &read_file(filename)?);
+                let mut warnings = Diagnostics::new();
+                let object = compile_with_warnings(&[source], &asm_options, &mut warnings)
+                    .map_err(format_diagnostics)?;
+                eprint!("{}", warnings);
+                write_file(&options.object_output(filename), object.write())?;
             }
+            return Ok(());
         }
+        Mode::Link => {
+            let mut objects = vec![];
+            let mut archives = vec![];
+            for filename in options.sources.iter() {
+                let text = read_file(filename)?;
+                let error = |error| format!("error: {}: {}", filename, error);
+                if Archive::is_archive(&text) {
+                    archives.push(Archive::read(&text).map_err(error)?);
+                } else {
+                    objects.push(ObjectFile::read(&text).map_err(error)?);
+                }
+            }
+            link_with_archives(objects, archives, load_ldscript(options)?, &asm_options)
+                .map_err(format_diagnostics)?
+        }
+        Mode::Archive => {
+            let mut archive = Archive::new();
+            for filename in options.sources.iter() {
+                let object = ObjectFile::read(&read_file(filename)?)
+                    .map_err(|error| format!("error: {}: {}", filename, error))?;
+                archive.add_member(&member_name(filename), object);
+            }
+            return write_file(options.output(), archive.write());
+        }
+    };
+    let image = program.image();
+
+    let output = match options.format {
+        OutputFormat::Binary => image.to_binary(),
+        OutputFormat::IntelHex => image.to_intel_hex().into_bytes(),
+        OutputFormat::SRecord => {
+            let header = srecord_header(options.output());
+            image.to_srecord(&header).into_bytes()
+        }
+    };
+    write_file(options.output(), output)?;
+
+    if let Some(filename) = &options.listing {
+        write_file(filename, program.listing())?;
+    }
+    if let Some(filename) = &options.map {
+        write_file(filename, program.map())?;
     }
+    if let Some(filename) = &options.symbols {
+        write_file(filename, program.export_symbols(options.symbol_format))?;
+    }
+    Ok(())
+}
+
+fn defines_source(options: &cli::Options) -> Source {
+    Source::new("<command line>", &options.defines_source())
+}
+
+fn load_ldscript(options: &cli::Options) -> Result<LdScript, String> {
+    match &options.ldscript {
+        Some(filename) => ldscript::load(filename).map_err(format_diagnostics),
+        None => Ok(LdScript::new(vec![
+            LdSection::new("text", Some(0xe000)),
+            LdSection::new("data", None),
+        ])),
+    }
+}
+
+fn format_diagnostics(diagnostics: Diagnostics) -> String {
+    diagnostics.to_string().trim_end().to_string()
+}
+
+fn write_file<C: AsRef<[u8]>>(filename: &str, contents: C) -> Result<(), String> {
+    fs::write(filename, contents).map_err(|error| format!("error: {}: {}", filename, error))
+}
+
+fn member_name(filename: &str) -> String {
+    // like ar, members are known by their file name only
+    Path::new(filename)
+        .file_name()
+        .map_or(filename.into(), |name| name.to_string_lossy().into())
+}
+
+fn srecord_header(output: &str) -> String {
+    // the S0 record conventionally holds the name of the file
+    Path::new(output)
+        .file_name()
+        .map_or(output.into(), |name| name.to_string_lossy().into())
+}
+
+fn read_file(filename: &str) -> Result<String, String> {
+    fs::read_to_string(filename).map_err(|error| format!("error: {}: {}", filename, error))
 }
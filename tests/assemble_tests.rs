@@ -0,0 +1,257 @@
+use retro_lang::{
+    assemble, assemble_program, assemble_with_options, compile, link, AsmOptions, Dialect,
+    LdScript, LdSection, ObjectFile, Source,
+};
+use std::fs;
+
+#[test]
+fn assemble_sections_into_segments() {
+    let sources = vec![
+        Source::new("main.s", "lda msg\nrts\n"),
+        Source::new("data.s", "section data\n.global msg\nmsg:\n.byte 1, 2\n"),
+    ];
+    let ldscript = LdScript::new(vec![
+        LdSection::new("text", Some(0xe000)),
+        LdSection::new("data", Some(0xe010)),
+    ]);
+    let image = assemble(&sources, ldscript).unwrap();
+
+    let segments: Vec<(u16, &[u8])> = image
+        .segments()
+        .iter()
+        .map(|segment| (segment.addr(), segment.data()))
+        .collect();
+    assert_eq!(
+        segments,
+        vec![
+            (0xe000, &[0xad, 0x10, 0xe0, 0x60][..]),
+            (0xe010, &[0x01, 0x02][..]),
+        ]
+    );
+
+    let binary = image.to_binary();
+    assert_eq!(binary.len(), 0x12);
+    assert_eq!(binary[0x04..0x10], [0; 12]);
+}
+
+#[test]
+fn assemble_reports_diagnostics() {
+    let sources = vec![
+        Source::new("a.s", "lda #$100\n"),
+        Source::new("b.s", "\nsta (ptr),x\n"),
+    ];
+    let diagnostics = assemble(
+        &sources,
+        LdScript::new(vec![LdSection::new("text", Some(0))]),
+    )
+    .unwrap_err();
+
+    let locations: Vec<(Option<&str>, Option<u32>)> =
+        diagnostics.iter().map(|d| (d.file(), d.line())).collect();
+    assert_eq!(
+        locations,
+        vec![(Some("a.s"), Some(1)), (Some("b.s"), Some(2))]
+    );
+    assert_eq!(
+        diagnostics.iter().next().unwrap().to_string(),
+        "a.s:1: error: immediate value does not fit into 8 bits"
+    );
+
+    let sources = vec![Source::new("c.s", "jmp nowhere\n")];
+    let diagnostics = assemble(
+        &sources,
+        LdScript::new(vec![LdSection::new("text", Some(0))]),
+    )
+    .unwrap_err();
+    // found while linking, still located at the referring line
+    let diagnostic = diagnostics.iter().next().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        (diagnostic.file(), diagnostic.line()),
+        (Some("c.s"), Some(1))
+    );
+    assert_eq!(
+        diagnostic.to_string(),
+        "c.s:1: error: undefined reference to symbol nowhere"
+    );
+}
+
+#[test]
+fn assemble_includes() {
+    let dir = std::env::temp_dir().join(format!("retro-lang-includes-{}", std::process::id()));
+    fs::create_dir_all(dir.join("inc")).unwrap();
+    fs::write(
+        dir.join("inc/consts.s"),
+        "VALUE = $42\n.include \"more.s\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("inc/more.s"), "section data\n.byte VALUE\n").unwrap();
+    fs::write(dir.join("inc/self.s"), ".include \"self.s\"\n").unwrap();
+
+    let sources = vec![Source::new("main.s", ".include \"consts.s\"\nlda #VALUE\n")];
+    let ldscript = LdScript::new(vec![
+        LdSection::new("text", Some(0x8000)),
+        LdSection::new("data", None),
+    ]);
+    let options = AsmOptions {
+        include_paths: vec![dir.join("inc")],
+        ..Default::default()
+    };
+    let image = assemble_with_options(&sources, ldscript, &options).unwrap();
+    // the included file switched to the data section, which sticks
+    assert_eq!(image.to_binary(), vec![0x42, 0xa9, 0x42]);
+
+    let sources = vec![Source::new("main.s", "\n.include \"self.s\"\n")];
+    let diagnostics = assemble_with_options(
+        &sources,
+        LdScript::new(vec![LdSection::new("text", Some(0))]),
+        &options,
+    )
+    .unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    let diagnostic = diagnostics.iter().next().unwrap();
+    assert_eq!(
+        diagnostic.file(),
+        Some(dir.join("inc/self.s").to_str().unwrap())
+    );
+    assert_eq!(diagnostic.line(), Some(1));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn assemble_entry_point() {
+    let sources = vec![Source::new(
+        "main.s",
+        ".global reset\nnop\nreset:\njmp reset\n",
+    )];
+    let ldscript = || LdScript::new(vec![LdSection::new("text", Some(0xe000))]);
+    let mut options = AsmOptions {
+        entry: Some("reset".into()),
+        ..Default::default()
+    };
+    let image = assemble_with_options(&sources, ldscript(), &options).unwrap();
+    assert_eq!(image.entry(), Some(0xe001));
+    assert!(image.to_srecord("main").ends_with("S903E0011B\n"));
+
+    options.entry = Some("start".into());
+    let diagnostics = assemble_with_options(&sources, ldscript(), &options).unwrap_err();
+    assert_eq!(
+        diagnostics.to_string().trim_end(),
+        "error: entry symbol start is not defined"
+    );
+}
+
+#[test]
+fn compile_and_link_separately() {
+    let options = AsmOptions::default();
+    let main = compile(
+        &[Source::new(
+            "main.s",
+            ".extern init, main\njsr init\njmp main\n",
+        )],
+        &options,
+    )
+    .unwrap();
+    let lib = compile(
+        &[Source::new(
+            "lib.s",
+            ".global main, init\nmain:\ninit: rts\n",
+        )],
+        &options,
+    )
+    .unwrap();
+
+    let objects = vec![
+        ObjectFile::read(&main.write()).unwrap(),
+        ObjectFile::read(&lib.write()).unwrap(),
+    ];
+    let ldscript = LdScript::new(vec![LdSection::new("text", Some(0x8000))]);
+    let program = link(objects, ldscript, &options).unwrap();
+    assert_eq!(
+        program.image().to_binary(),
+        vec![0x20, 0x06, 0x80, 0x4c, 0x06, 0x80, 0x60]
+    );
+
+    let diagnostics = compile(&[Source::new("bad.s", "lda #$100\n")], &options)
+        .err()
+        .unwrap();
+    assert_eq!(diagnostics.len(), 1);
+}
+
+#[test]
+fn assemble_local_symbols() {
+    let ldscript = || LdScript::new(vec![LdSection::new("text", Some(0x8000))]);
+    let sources = vec![
+        Source::new("main.s", "COUNT = 2\nloop: jsr delay\nbra loop\n"),
+        Source::new(
+            "delay.s",
+            ".global delay\nCOUNT = 8\ndelay: ldx #COUNT\nloop: dex\nbne loop\nrts\n",
+        ),
+    ];
+    let image = assemble(&sources, ldscript()).unwrap();
+    assert_eq!(
+        image.to_binary(),
+        vec![0x20, 0x05, 0x80, 0x80, 0xfb, 0xa2, 0x08, 0xca, 0xd0, 0xfd, 0x60]
+    );
+
+    let sources = vec![
+        Source::new("a.s", ".global start\nstart: nop\n"),
+        Source::new("b.s", "nop\n.export start\nstart: rts\n"),
+        Source::new("c.s", "jmp start\n"),
+    ];
+    let diagnostics = assemble(&sources, ldscript()).unwrap_err();
+    assert_eq!(
+        diagnostics.to_string().trim_end(),
+        "error: duplicate global symbol start: defined at a.s:2 and at b.s:3"
+    );
+
+    let options = AsmOptions {
+        defines: Some(Source::new("<command line>", "COUNT = 3\n")),
+        ..Default::default()
+    };
+    let sources = vec![
+        Source::new("a.s", "lda #COUNT\n"),
+        Source::new("b.s", "ldx #COUNT\n"),
+    ];
+    let image = assemble_with_options(&sources, ldscript(), &options).unwrap();
+    assert_eq!(image.to_binary(), vec![0xa9, 0x03, 0xa2, 0x03]);
+}
+
+#[test]
+fn assemble_with_warnings() {
+    let ldscript = || LdScript::new(vec![LdSection::new("text", Some(0x8000))]);
+    let sources = vec![Source::new("main.s", "jsr r3\nrts\nr3: rts\n")];
+    let program = assemble_program(&sources, ldscript(), &AsmOptions::default()).unwrap();
+    assert_eq!(
+        program.image().to_binary(),
+        vec![0x20, 0x04, 0x80, 0x60, 0x60]
+    );
+    assert_eq!(
+        program.warnings().to_string(),
+        "main.s:3: warning: symbol r3 shadows the pseudo register\n"
+    );
+
+    let sources = vec![Source::new("main.s", "r3: nop\nr3: rts\n")];
+    let diagnostics = assemble(&sources, ldscript()).unwrap_err();
+    assert_eq!(
+        diagnostics.to_string(),
+        "main.s:2: error: symbol r3 is already defined at main.s:1\n\
+         main.s:1: warning: symbol r3 shadows the pseudo register\n"
+    );
+}
+
+#[test]
+fn assemble_with_semicolon_comments() {
+    let ldscript = || LdScript::new(vec![LdSection::new("text", Some(0x8000))]);
+    let sources = vec![Source::new("main.s", "inx ; inc\ndex; rts\n")];
+    let image = assemble(&sources, ldscript()).unwrap();
+    assert_eq!(image.to_binary(), vec![0xe8, 0x1a, 0xca, 0x60]);
+
+    let options = AsmOptions {
+        dialect: Dialect::SemicolonComment,
+        ..Default::default()
+    };
+    let image = assemble_with_options(&sources, ldscript(), &options).unwrap();
+    assert_eq!(image.to_binary(), vec![0xe8, 0xca]);
+}